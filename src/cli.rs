@@ -1,6 +1,8 @@
 ﻿use std::ffi::OsStr;
 use clap::{Args, Parser, Subcommand, ValueEnum};
 use glob::glob;
+use http_snap::error::HttpSnapError;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -9,7 +11,7 @@ use std::path::PathBuf;
     version = "1.0"
 )]
 pub(crate) struct Cli {
-    /// Subcommand to run (`test` or `update`)
+    /// Subcommand to run (`test`, `update`, `serve` or `record`)
     #[command(subcommand)]
     pub(crate) command: Commands,
 }
@@ -29,12 +31,40 @@ pub(crate) struct GlobalOptions {
     #[arg(short, long)]
     pub(crate) environment: Option<PathBuf>,
 
+    /// Name of the environment to select from a JSON environment file, which is
+    /// `http-snap.env.json` unless another one is passed with `--environment`
+    #[arg(long = "env", value_name = "NAME")]
+    pub(crate) env_name: Option<String>,
+
+    /// `.env` file to load into the process environment, read with `{{$env.NAME}}`.
+    /// Defaults to `.env` in the working directory, if there is one
+    #[arg(long)]
+    pub(crate) env_file: Option<PathBuf>,
+
     /// Json file containing client options
     #[arg(short, long)]
     pub(crate) client_options: Option<PathBuf>,
+
+    /// Write a report as `kind=path`, where kind is `junit` or `json`.
+    /// Can be specified multiple times
+    #[arg(long = "report", value_name = "KIND=PATH", value_parser = parse_report_target)]
+    pub(crate) reports: Vec<ReportTarget>,
+
+    /// Number of files to run concurrently
+    #[arg(short, long, default_value_t = NonZeroUsize::MIN)]
+    pub(crate) jobs: NonZeroUsize,
+
+    /// Compare numbers by value, so `1` equals `1.0` and rounding noise in the last digit is ignored
+    #[arg(long)]
+    pub(crate) numeric_equality: bool,
+
+    /// Seed for generated variables, to replay a run with the same values.
+    /// A random seed is used when omitted, and printed in the summary
+    #[arg(long)]
+    pub(crate) seed: Option<u64>,
 }
 
-/// Enum of subcommands (test and update)
+/// Enum of subcommands (test, update, serve and record)
 #[derive(Debug, Subcommand)]
 pub(crate) enum Commands {
     /// Runs all tests and compares responses to snapshots
@@ -51,6 +81,52 @@ pub(crate) enum Commands {
         #[command(flatten)]
         options: UpdateOptions,
     },
+
+    /// Starts a mock server that replays the snapshots as responses
+    Serve {
+        /// File path or directory to read snapshots from (supports wildcards)
+        #[arg(long, required = true)]
+        path: PathBuf,
+
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Only replay a snapshot when the request body matches the recorded one
+        #[arg(long)]
+        match_body: bool,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// File containing environment variables
+        #[arg(short, long)]
+        environment: Option<PathBuf>,
+    },
+
+    /// Runs a proxy that writes each request and its response into a new file
+    Record {
+        /// Base url to forward requests to, e.g. http://localhost:5000
+        #[arg(long, required = true)]
+        target: String,
+
+        /// Directory to write the recorded files into
+        #[arg(long, required = true)]
+        out: PathBuf,
+
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Write `{{name}}` instead of the target url, so the files can run against other hosts
+        #[arg(long)]
+        host_variable: Option<String>,
+
+        /// Enable verbose output
+        #[arg(short, long)]
+        verbose: bool,
+    },
 }
 
 #[derive(Debug, Args)]
@@ -66,6 +142,10 @@ pub struct UpdateOptions {
     /// Choose which detectors to run. Can be specified multiple times
     #[arg(long, value_enum, value_delimiter = ',', num_args = 1..)]
     pub(crate) detectors: Vec<Detector>,
+
+    /// Write response bodies longer than this many lines to a separate snapshot file
+    #[arg(long, value_name = "LINES")]
+    pub(crate) body_file_threshold: Option<usize>,
 }
 
 #[derive(Debug, ValueEnum, Clone, PartialEq, Eq)]
@@ -81,32 +161,64 @@ pub enum Detector {
     Guid,
 }
 
-pub(crate) fn expand_paths(path: PathBuf) -> Vec<PathBuf> {
+#[derive(Debug, ValueEnum, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+    Junit,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ReportTarget {
+    pub(crate) kind: ReportKind,
+    pub(crate) path: PathBuf,
+}
+
+fn parse_report_target(value: &str) -> Result<ReportTarget, String> {
+    let Some((kind, path)) = value.split_once('=') else {
+        return Err(format!("expected kind=path but got \"{value}\""));
+    };
+    return Ok(ReportTarget {
+        kind: ReportKind::from_str(kind, true)?,
+        path: PathBuf::from(path),
+    });
+}
+
+pub(crate) fn expand_paths(path: PathBuf) -> Result<Vec<PathBuf>, HttpSnapError> {
     let mut expanded = Vec::new();
     let path_str = path.to_string_lossy();
     // Check if the path contains a wildcard
     if path_str.contains('*') {
         // Use the glob crate to find matching files
-        match glob(&path_str) {
-            Ok(paths) => {
-                for entry in paths {
-                    match entry {
-                        Ok(p) if is_supported_file(&p) => expanded.push(p),
-                        Ok(_) => continue,
-                        Err(e) => log::error!("Error expanding path: {}", e),
-                    }
+        let paths = glob(&path_str).map_err(|e| HttpSnapError::InvalidPath {
+            path: path.clone(),
+            reason: e.to_string(),
+        })?;
+        for entry in paths {
+            match entry {
+                Ok(p) if is_supported_file(&p) => expanded.push(p),
+                Ok(_) => continue,
+                Err(e) => {
+                    return Err(HttpSnapError::Io {
+                        path: e.path().to_path_buf(),
+                        source: e.into(),
+                    })
                 }
             }
-            Err(e) => log::error!("Glob pattern error: {}", e),
+        }
+        // A pattern without matches is most likely a typo, which should not pass silently
+        if expanded.is_empty() {
+            return Err(HttpSnapError::InvalidPath {
+                path,
+                reason: String::from("no .http or .md files match"),
+            });
         }
     } else {
-        if is_supported_file(&path) {
-            expanded.push(path);
-        } else {
-            panic!("Unsupported file type {}", path.display());
+        if !is_supported_file(&path) {
+            return Err(HttpSnapError::UnsupportedFile(path));
         }
+        expanded.push(path);
     }
-    return expanded;
+    return Ok(expanded);
 }
 
 fn is_supported_file(path: &PathBuf) -> bool {
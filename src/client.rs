@@ -1,17 +1,27 @@
-﻿use crate::types::{ClientOptions, Header, HttpFile, HttpVerb, Json};
-use reqwest::header::{HeaderMap, HeaderName};
-use reqwest::{Client, Method};
+use crate::error::HttpSnapError;
+use crate::types::{
+    ClientOptions, FormField, Header, HttpFile, HttpVerb, Json, MultipartPart, PartContent,
+    QueryParameter, RequestBody,
+};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Method, Url};
+use std::path::PathBuf;
 
 pub struct HttpClient {
     client: Client,
-    options: ClientOptions
+    options: ClientOptions,
 }
 
 #[derive(Debug)]
 pub struct HttpResponse {
     pub status: u16,
     pub headers: HeaderMap,
-    pub body: String,
+    pub body: Vec<u8>,
+    /// Url that was requested, including the encoded query parameters
+    pub request_url: String,
+    /// Headers that were sent with the request
+    pub request_headers: HeaderMap,
 }
 
 impl HttpClient {
@@ -20,17 +30,21 @@ impl HttpClient {
             .cookie_store(options.use_cookies.unwrap_or(true))
             .build()
             .expect("Failed to build client");
-        return Self { client, options: options.clone() };
+        return Self {
+            client,
+            options: options.clone(),
+        };
     }
 
-    pub async fn send_request(
-        &self,
-        http_file: &HttpFile,
-    ) -> Result<HttpResponse, Box<dyn std::error::Error>> {
-        let headers = self.get_headers(&http_file.headers);
-        let body = get_json(&http_file.body);
+    pub async fn send_request(&self, http_file: &HttpFile) -> Result<HttpResponse, HttpSnapError> {
+        let mut request_headers = self.get_headers(&http_file.headers)?;
+        if let Some(RequestBody::Multipart(_)) = &http_file.body {
+            // reqwest sets the header itself, with the boundary it generates
+            request_headers.remove(CONTENT_TYPE);
+        }
 
-        let url = &http_file.url.to_string();
+        let url = get_url(&http_file.url.to_string(), &http_file.query_parameters)?;
+        let request_url = url.to_string();
         let verb_setup = match http_file.verb {
             HttpVerb::CONNECT => self.client.request(Method::CONNECT, url),
             HttpVerb::DELETE => self.client.delete(url),
@@ -42,40 +56,136 @@ impl HttpClient {
             HttpVerb::PUT => self.client.put(url),
             HttpVerb::TRACE => self.client.request(Method::TRACE, url),
         };
-        let response = verb_setup.headers(headers).body(body).send().await?;
+        let with_headers = verb_setup.headers(request_headers.clone());
+        let with_body = match &http_file.body {
+            Some(RequestBody::Json(json)) => with_headers.body(get_json(Some(json))?),
+            Some(RequestBody::Form(fields)) => with_headers.body(encode_form(fields)),
+            Some(RequestBody::Raw(text)) => with_headers.body(text.to_string()),
+            Some(RequestBody::Multipart(parts)) => with_headers.multipart(get_form(parts).await?),
+            Some(RequestBody::File(path)) => with_headers.body(read_file(path).await?),
+            None => with_headers.body(get_json(None)?),
+        };
+        let response = with_body.send().await?;
 
         let status = response.status().as_u16();
         let headers = response.headers().clone();
-        let body = response.text().await?;
+        let body = response.bytes().await?.to_vec();
         return Ok(HttpResponse {
             status,
             headers,
             body,
+            request_url,
+            request_headers,
         });
     }
 
-    fn get_headers(&self, request_headers: &Vec<Header>) -> HeaderMap {
+    fn get_headers(&self, request_headers: &Vec<Header>) -> Result<HeaderMap, HttpSnapError> {
         let mut headers = HeaderMap::new();
 
         if let Some(default_headers) = &self.options.default_headers {
             for header in default_headers {
-                headers.insert(
-                    HeaderName::from_bytes(header.name.as_ref()).unwrap(),
-                    header.value.parse().unwrap(),
-                );
+                let (name, value) = to_header(&header.name, &header.value)?;
+                headers.insert(name, value);
             }
         }
 
         for header in request_headers {
-            headers.insert(
-                HeaderName::from_bytes(header.name.as_ref()).unwrap(),
-                header.value.to_string().parse().unwrap(),
-            );
+            let (name, value) = to_header(&header.name, &header.value.to_string())?;
+            headers.insert(name, value);
         }
-        return headers;
+        return Ok(headers);
+    }
+}
+
+fn to_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue), HttpSnapError> {
+    let invalid = |reason: String| HttpSnapError::InvalidHeader {
+        name: name.to_string(),
+        reason,
+    };
+    let header_name =
+        HeaderName::from_bytes(name.as_ref()).map_err(|error| invalid(error.to_string()))?;
+    let header_value = value
+        .parse::<HeaderValue>()
+        .map_err(|error| invalid(error.to_string()))?;
+    return Ok((header_name, header_value));
+}
+
+fn get_url(url: &str, query_parameters: &Vec<QueryParameter>) -> Result<Url, HttpSnapError> {
+    let mut parsed = Url::parse(url).map_err(|error| HttpSnapError::InvalidUrl {
+        url: url.to_string(),
+        reason: error.to_string(),
+    })?;
+    if !query_parameters.is_empty() {
+        let mut pairs = parsed.query_pairs_mut();
+        for parameter in query_parameters {
+            let name = parameter.name.to_string();
+            match &parameter.value {
+                Some(value) => pairs.append_pair(&name, &value.to_string()),
+                None => pairs.append_key_only(&name),
+            };
+        }
+    }
+    return Ok(parsed);
+}
+
+async fn get_form(parts: &Vec<MultipartPart>) -> Result<Form, HttpSnapError> {
+    let mut form = Form::new();
+    for part in parts {
+        let header = |name: &str| {
+            return part
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .map(|header| header.value.to_string());
+        };
+        let disposition = header("content-disposition").unwrap_or_default();
+        let Some(name) = disposition_parameter(&disposition, "name") else {
+            return Err(HttpSnapError::InvalidBody(String::from(
+                "Multipart part is missing a Content-Disposition header with a name",
+            )));
+        };
+
+        let mut form_part = match &part.content {
+            PartContent::Text(text) => Part::text(text.to_string()),
+            PartContent::File(path) => Part::bytes(read_file(path).await?),
+        };
+        if let Some(file_name) = disposition_parameter(&disposition, "filename") {
+            form_part = form_part.file_name(file_name);
+        }
+        if let Some(content_type) = header("content-type") {
+            form_part = form_part
+                .mime_str(&content_type)
+                .map_err(|error| HttpSnapError::InvalidBody(error.to_string()))?;
+        }
+        form = form.part(name, form_part);
+    }
+    return Ok(form);
+}
+
+async fn read_file(path: &PathBuf) -> Result<Vec<u8>, HttpSnapError> {
+    return tokio::fs::read(path)
+        .await
+        .map_err(|error| HttpSnapError::io(path, error));
+}
+
+// Reads a parameter like `name` from `form-data; name="file"; filename="data.json"`
+fn disposition_parameter(disposition: &str, parameter: &str) -> Option<String> {
+    return disposition
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case(parameter))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string());
+}
+
+fn encode_form(fields: &Vec<FormField>) -> String {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for field in fields {
+        serializer.append_pair(&field.name.to_string(), &field.value.to_string());
     }
+    return serializer.finish();
 }
 
-fn get_json(body: &Option<Json>) -> String {
-    return serde_json::to_string(body).unwrap();
+fn get_json(body: Option<&Json>) -> Result<String, HttpSnapError> {
+    return serde_json::to_string(&body)
+        .map_err(|error| HttpSnapError::InvalidBody(error.to_string()));
 }
@@ -1,127 +1,257 @@
-﻿use crate::types::{
-    Array, Comparison, CompositeString, Element, Header, Json, Number, Object, SnapResponse,
-    Snapshot, Status, Value,
+use crate::parser;
+use crate::types::{
+    Array, CompareOptions, Comparison, CompositeString, Element, Header, Mismatch, MismatchReason,
+    Number, NumericComparison, Object, ResponseBody, SnapResponse, Snapshot, Status, Value,
+    ValueType,
 };
 use chrono::{DateTime, NaiveDateTime};
 use itertools::Itertools;
-use std::collections::HashMap;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
-pub fn compare_to_snapshot(snapshot: &Snapshot, response: &SnapResponse) -> bool {
-    let status_matches = match_status(&snapshot.status, &response.status);
-    if !status_matches {
-        log::error!("Status did not match snapshot");
-        log::error!(
-            "Expected: {:?} but got {:?}",
-            snapshot.status,
-            response.status
-        );
-        return false;
-    }
+/// Compares the response to the snapshot and returns every difference found.
+/// An empty list means that the response matches the snapshot.
+pub fn compare_to_snapshot(
+    snapshot: &Snapshot,
+    response: &SnapResponse,
+    options: &CompareOptions,
+) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    match_status(&snapshot.status, &response.status, &mut mismatches);
+    match_headers(&snapshot.headers, &response.headers, &mut mismatches);
+    match_body(&snapshot.body, &response.body, options, &mut mismatches);
+    return mismatches;
+}
 
-    let headers_match = match_headers(&snapshot.headers, &response.headers);
-    if !headers_match {
-        return false;
-    }
+pub(crate) fn status_matches(snapshot_status: &Status, response_status: &u16) -> bool {
+    let mut mismatches = Vec::new();
+    match_status(snapshot_status, response_status, &mut mismatches);
+    return mismatches.is_empty();
+}
 
-    let body_match = match_body(&snapshot.body, &response.body);
-    if !body_match {
-        log::error!("Body did not match snapshot");
-        return false;
-    }
+pub(crate) fn header_matches(snapshot_header: &Header, response_header: &Header) -> bool {
+    let mut mismatches = Vec::new();
+    let response_headers = HashMap::from([(response_header.name.clone(), response_header.clone())]);
+    match_headers(
+        &vec![snapshot_header.clone()],
+        &response_headers,
+        &mut mismatches,
+    );
+    return mismatches.is_empty();
+}
 
-    return true;
+pub(crate) fn element_matches(
+    expected: &Element,
+    actual: &Element,
+    options: &CompareOptions,
+) -> bool {
+    let mut mismatches = Vec::new();
+    match_body_element(expected, actual, "", options, &mut mismatches);
+    return mismatches.is_empty();
 }
 
-fn match_status(snapshot_status: &Status, response_status: &u16) -> bool {
-    return match snapshot_status {
+fn match_status(snapshot_status: &Status, response_status: &u16, mismatches: &mut Vec<Mismatch>) {
+    let matched = match snapshot_status {
         Status::Value(Number::Int(value)) => value == &(response_status.clone() as i64),
         Status::Pattern(pattern) => {
             let code = response_status.to_string();
-            for (index, number) in code.chars().enumerate() {
-                let pattern_number = pattern.chars().nth(index).unwrap();
-                if pattern_number != 'x' && pattern_number != 'X' {
-                    if pattern_number != number {
-                        return false;
-                    }
-                }
-            }
-            true
+            code.chars()
+                .zip(pattern.chars())
+                .all(|(number, pattern_number)| {
+                    pattern_number == 'x' || pattern_number == 'X' || pattern_number == number
+                })
         }
         _ => false,
     };
+
+    if !matched {
+        let expected = match snapshot_status {
+            Status::Value(value) => value.to_string(),
+            Status::Pattern(pattern) => pattern.clone(),
+        };
+        mismatches.push(Mismatch::new(
+            String::from("/status"),
+            expected,
+            response_status.to_string(),
+            MismatchReason::NotEqual,
+        ));
+    }
 }
 
 fn match_headers(
     snapshot_headers: &Vec<Header>,
     response_header: &HashMap<String, Header>,
-) -> bool {
+    mismatches: &mut Vec<Mismatch>,
+) {
     for snapshot_header in snapshot_headers {
-        if matches!(snapshot_header.comparison, Some(Comparison::Ignore)) {
+        // An ignored header still has to be present when its value is stored in a variable
+        let ignored = matches!(snapshot_header.comparison, Some(Comparison::Ignore));
+        if ignored && snapshot_header.variable_store.is_none() {
             continue;
         }
 
-        let matched_snapshot = match &snapshot_header.comparison {
+        let path = pointer("/headers", &snapshot_header.name);
+        let expected = describe_header(snapshot_header);
+        let Some(response_value) = response_header.get(&snapshot_header.name) else {
+            mismatches.push(Mismatch::new(
+                path,
+                expected,
+                String::new(),
+                MismatchReason::Missing,
+            ));
+            continue;
+        };
+
+        let value = response_value.value.to_string();
+        let reason = match &snapshot_header.comparison {
+            Some(Comparison::Ignore) => None,
             Some(Comparison::Exact) => {
-                let response_value_option = response_header.get(&snapshot_header.name);
-                if let Some(response_value) = response_value_option {
-                    response_value.value.to_string() == snapshot_header.value.to_string()
-                } else {
-                    false
-                }
+                (value != snapshot_header.value.to_string()).then_some(MismatchReason::NotEqual)
             }
             Some(Comparison::TimestampFormat(pattern)) => {
-                let response_value_option = response_header.get(&snapshot_header.name);
-                if let Some(response_value) = response_value_option {
-                    let value = response_value.value.to_string();
-                    compare_timestamp_format(pattern, &value)
-                } else {
-                    false
-                }
+                (!compare_timestamp_format(pattern, &value))
+                    .then_some(MismatchReason::InvalidFormat)
             }
             Some(Comparison::Guid) => {
-                let response_value_option = response_header.get(&snapshot_header.name);
-                if let Some(response_value) = response_value_option {
-                    let value = response_value.value.to_string();
-                    compare_guid_format(&value)
-                } else {
-                    false
-                }
+                (!compare_guid_format(&value)).then_some(MismatchReason::InvalidFormat)
             }
-            _ => false,
+            Some(Comparison::Regex(pattern)) => {
+                (!compare_regex(pattern, &value)).then_some(MismatchReason::InvalidFormat)
+            }
+            _ => Some(MismatchReason::NotEqual),
         };
 
-        if !matched_snapshot {
-            log_header_mismatch(snapshot_header, response_header.get(&snapshot_header.name));
-            return false;
+        if let Some(reason) = reason {
+            mismatches.push(Mismatch::new(path, expected, value, reason));
         }
     }
 
-    if response_header.len() > snapshot_headers.len() {
-        log::error!("Response contains headers not present in snapshot");
-        return false;
+    let snapshot_names = snapshot_headers
+        .iter()
+        .map(|header| &header.name)
+        .collect::<HashSet<_>>();
+    for name in response_header.keys().sorted() {
+        if !snapshot_names.contains(name) {
+            mismatches.push(Mismatch::new(
+                pointer("/headers", name),
+                String::new(),
+                response_header[name].value.to_string(),
+                MismatchReason::Unexpected,
+            ));
+        }
     }
-
-    return true;
 }
 
-fn match_body(snapshot_body: &Option<Json>, response_body: &Option<Json>) -> bool {
-    return match (snapshot_body, response_body) {
-        (None, None) => true,
+fn match_body(
+    snapshot_body: &Option<ResponseBody>,
+    response_body: &Option<ResponseBody>,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    // A text snapshot can look like JSON, for example a plain number, and is then parsed as such
+    let parsed_text = match (snapshot_body, response_body) {
+        (Some(ResponseBody::Json(_)), Some(ResponseBody::Text(text))) => {
+            parser::parse_json(text).map(ResponseBody::Json)
+        }
+        _ => None,
+    };
+    let response_body = parsed_text.as_ref().or(response_body.as_ref());
+
+    let path = String::from("/body");
+    let body_mismatch = |snapshot, response, reason| {
+        Mismatch::new(
+            String::from("/body"),
+            describe_body(snapshot),
+            describe_body(response),
+            reason,
+        )
+    };
+    match (snapshot_body.as_ref(), response_body) {
+        (None, None) => (),
+        (Some(ResponseBody::Json(snapshot)), Some(ResponseBody::Json(response))) => {
+            match_body_element(
+                &snapshot.element,
+                &response.element,
+                &path,
+                options,
+                mismatches,
+            )
+        }
+        (
+            Some(snapshot @ ResponseBody::Text(expected)),
+            Some(response @ ResponseBody::Text(actual)),
+        ) => {
+            if expected.trim_end() != actual.trim_end() {
+                mismatches.push(body_mismatch(snapshot, response, MismatchReason::NotEqual));
+            }
+        }
+        (
+            Some(snapshot @ ResponseBody::Binary { size, sha256 }),
+            Some(
+                response @ ResponseBody::Binary {
+                    size: actual_size,
+                    sha256: actual_sha256,
+                },
+            ),
+        ) => {
+            if size != actual_size || sha256 != actual_sha256 {
+                mismatches.push(body_mismatch(snapshot, response, MismatchReason::NotEqual));
+            }
+        }
         (Some(snapshot), Some(response)) => {
-            match_body_element(&snapshot.element, &response.element)
+            mismatches.push(body_mismatch(snapshot, response, MismatchReason::WrongType))
         }
-        _ => false,
+        (Some(snapshot), None) => mismatches.push(Mismatch::new(
+            path,
+            describe_body(snapshot),
+            String::new(),
+            MismatchReason::Missing,
+        )),
+        (None, Some(response)) => mismatches.push(Mismatch::new(
+            path,
+            String::new(),
+            describe_body(response),
+            MismatchReason::Unexpected,
+        )),
     };
 }
 
-fn match_body_element(expected: &Element, actual: &Element) -> bool {
-    return match &expected.comparison {
+fn match_body_element(
+    expected: &Element,
+    actual: &Element,
+    path: &str,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let matched_format = match &expected.comparison {
         Some(Comparison::Ignore) => true,
         Some(Comparison::TimestampFormat(pattern)) => match_body_timestamp(&pattern, &actual.value),
         Some(Comparison::Guid) => match_body_guid(&actual.value),
-        _ => match_body_value(&expected.value, &actual.value), // This is the same as exact
+        Some(Comparison::Regex(pattern)) => match_body_regex(pattern, &actual.value),
+        Some(Comparison::Type(value_type)) => {
+            if !match_body_type(value_type, &actual.value) {
+                mismatches.push(Mismatch::new(
+                    path.to_string(),
+                    describe_element(expected),
+                    describe_element(actual),
+                    MismatchReason::WrongType,
+                ));
+            }
+            return;
+        }
+        Some(Comparison::Numeric(comparison)) => match_body_numeric(comparison, &actual.value),
+        _ => return match_body_value(&expected.value, &actual.value, path, options, mismatches), // This is the same as exact
     };
+
+    if !matched_format {
+        mismatches.push(Mismatch::new(
+            path.to_string(),
+            describe_element(expected),
+            describe_element(actual),
+            MismatchReason::InvalidFormat,
+        ));
+    }
 }
 
 fn match_body_timestamp(pattern: &CompositeString, actual: &Value) -> bool {
@@ -129,50 +259,88 @@ fn match_body_timestamp(pattern: &CompositeString, actual: &Value) -> bool {
         Value::String(actual_string) => {
             compare_timestamp_format(pattern, &actual_string.to_string())
         }
-        _ => {
-            log::error!("Value {:?} is not a string", actual);
-            false
-        }
+        _ => false,
     };
 }
 
 fn match_body_guid(actual: &Value) -> bool {
     return match actual {
         Value::String(actual_string) => compare_guid_format(&actual_string.to_string()),
-        _ => {
-            log::error!("Value {:?} is not a string", actual);
-            false
-        }
+        _ => false,
     };
 }
 
-fn match_body_value(expected: &Value, actual: &Value) -> bool {
-    return match (expected, actual) {
+fn match_body_regex(pattern: &str, actual: &Value) -> bool {
+    return match actual {
+        Value::String(actual_string) => compare_regex(pattern, &actual_string.to_string()),
+        _ => false,
+    };
+}
+
+fn match_body_type(value_type: &ValueType, actual: &Value) -> bool {
+    return matches!(
+        (value_type, actual),
+        (ValueType::String, Value::String(_))
+            | (ValueType::Number, Value::Number(_))
+            | (ValueType::Integer, Value::Number(Number::Int(_)))
+            | (ValueType::Boolean, Value::Boolean(_))
+            | (ValueType::Array, Value::Array(_))
+            | (ValueType::Object, Value::Object(_))
+    );
+}
+
+fn match_body_value(
+    expected: &Value,
+    actual: &Value,
+    path: &str,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let matched = match (expected, actual) {
         (Value::Object(expected_object), Value::Object(actual_object)) => {
-            match_body_object(expected_object, actual_object)
+            return match_body_object(expected_object, actual_object, path, options, mismatches);
         }
         (Value::Array(expected_array), Value::Array(actual_array)) => {
-            match_body_array(expected_array, actual_array)
+            return match_body_array(expected_array, actual_array, path, options, mismatches);
         }
         (Value::String(expected_string), Value::String(actual_string)) => {
             expected_string.to_string() == actual_string.to_string()
         }
         (Value::Number(expected_number), Value::Number(actual_number)) => {
-            match_body_number(expected_number, actual_number)
+            match_body_number(expected_number, actual_number, options)
         }
         (Value::Boolean(expected_bool), Value::Boolean(actual_bool)) => {
             expected_bool == actual_bool
         }
         (Value::Null(), Value::Null()) => true,
-        _ => false,
+        _ => {
+            mismatches.push(Mismatch::new(
+                path.to_string(),
+                describe_value(expected),
+                describe_value(actual),
+                MismatchReason::WrongType,
+            ));
+            return;
+        }
     };
-}
 
-fn match_body_object(expected: &Object, actual: &Object) -> bool {
-    if expected.members.len() != actual.members.len() {
-        return false;
+    if !matched {
+        mismatches.push(Mismatch::new(
+            path.to_string(),
+            describe_value(expected),
+            describe_value(actual),
+            MismatchReason::NotEqual,
+        ));
     }
+}
 
+fn match_body_object(
+    expected: &Object,
+    actual: &Object,
+    path: &str,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
     let actual_members = actual
         .members
         .iter()
@@ -180,69 +348,159 @@ fn match_body_object(expected: &Object, actual: &Object) -> bool {
         .collect::<HashMap<_, _>>();
 
     for member in &expected.members {
-        let actual_member = actual_members.get(&member.key);
-        if actual_member.is_none() {
-            log::info!("Could not find expected member named {:?}", member.key);
-            return false;
+        let member_path = pointer(path, &member.key);
+        match actual_members.get(&member.key) {
+            Some(actual_member) => match_body_element(
+                &member.value,
+                &actual_member.value,
+                &member_path,
+                options,
+                mismatches,
+            ),
+            None => mismatches.push(Mismatch::new(
+                member_path,
+                describe_element(&member.value),
+                String::new(),
+                MismatchReason::Missing,
+            )),
         }
+    }
 
-        let matched_member = match_body_element(&member.value, &actual_member.unwrap().value);
-        if !matched_member {
-            log::info!("Member named: {:?} did NOT match snapshot", member.key);
-            return false;
+    let expected_keys = expected
+        .members
+        .iter()
+        .map(|member| &member.key)
+        .collect::<HashSet<_>>();
+    for member in &actual.members {
+        if !expected.partial && !expected_keys.contains(&member.key) {
+            mismatches.push(Mismatch::new(
+                pointer(path, &member.key),
+                String::new(),
+                describe_element(&member.value),
+                MismatchReason::Unexpected,
+            ));
         }
     }
-
-    return true;
 }
 
-fn match_body_array(expected: &Array, actual: &Array) -> bool {
-    return match expected {
-        Array::Literal(elements) => match_array_literal(elements, &actual.get_known_elements()),
-        Array::StartsWith(elements) => match_array_literal(
-            elements,
-            &actual
-                .get_known_elements()
-                .into_iter()
-                .take(elements.len())
-                .collect_vec(),
-        ),
-        Array::Contains(elements) => match_array_contains(elements, &actual.get_known_elements()),
+fn match_body_array(
+    expected: &Array,
+    actual: &Array,
+    path: &str,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let Ok(actual_elements) = actual.get_known_elements() else {
+        return mismatches.push(Mismatch::new(
+            path.to_string(),
+            describe_array(expected),
+            describe_array(actual),
+            MismatchReason::WrongType,
+        ));
+    };
+    let array_mismatch = |reason: MismatchReason| {
+        Mismatch::new(
+            path.to_string(),
+            describe_array(expected),
+            describe_array(actual),
+            reason,
+        )
+    };
+
+    match expected {
+        Array::Literal(elements) => {
+            match_array_literal(elements, &actual_elements, 0, path, options, mismatches);
+            for (index, element) in actual_elements.iter().enumerate().skip(elements.len()) {
+                mismatches.push(Mismatch::new(
+                    format!("{path}/{index}"),
+                    String::new(),
+                    describe_element(element),
+                    MismatchReason::Unexpected,
+                ));
+            }
+        }
+        Array::StartsWith(elements) => {
+            if elements.len() > actual_elements.len() {
+                mismatches.push(array_mismatch(MismatchReason::TooFewElements));
+            } else {
+                match_array_literal(elements, &actual_elements, 0, path, options, mismatches);
+            }
+        }
+        Array::Contains(elements) => {
+            if !match_array_contains(elements, &actual_elements, options) {
+                mismatches.push(array_mismatch(MismatchReason::NotFound));
+            }
+        }
         Array::EndsWith(elements) => {
-            let actual_elements = actual.get_known_elements();
-            return match_array_literal(
-                elements,
-                &actual_elements
-                    .clone()
-                    .into_iter()
-                    .skip(actual_elements.len() - elements.len())
-                    .take(elements.len())
-                    .collect_vec(),
-            );
-        }
-        Array::VariableReference(name) => panic!("Variable named {name} has not been replaced yet"),
+            if elements.len() > actual_elements.len() {
+                mismatches.push(array_mismatch(MismatchReason::TooFewElements));
+            } else {
+                let offset = actual_elements.len() - elements.len();
+                let tail = actual_elements.into_iter().skip(offset).collect_vec();
+                match_array_literal(elements, &tail, offset, path, options, mismatches);
+            }
+        }
+        Array::Unordered(elements) | Array::ContainsAll(elements) => {
+            if elements.len() > actual_elements.len() {
+                mismatches.push(array_mismatch(MismatchReason::TooFewElements));
+            } else if count_assigned(elements, &actual_elements, options) < elements.len() {
+                mismatches.push(array_mismatch(MismatchReason::NotFound));
+            } else if matches!(expected, Array::Unordered(_))
+                && elements.len() < actual_elements.len()
+            {
+                mismatches.push(array_mismatch(MismatchReason::Unexpected));
+            }
+        }
+        Array::VariableReference(name) => mismatches.push(Mismatch::new(
+            path.to_string(),
+            format!("{{{{{name}}}}}"),
+            describe_array(actual),
+            MismatchReason::WrongType,
+        )),
     };
 }
 
-fn match_array_literal(expected: &Vec<Element>, actual: &Vec<Element>) -> bool {
-    if expected.len() != actual.len() {
-        return false;
-    }
-
-    let zipped = expected.into_iter().zip(actual.into_iter());
-    for (expected_element, actual_element) in zipped {
-        let matches_expected = match_body_element(&expected_element, &actual_element);
-        if !matches_expected {
-            return false;
+// Compares element by element, where `offset` is the index of the first actual element
+fn match_array_literal(
+    expected: &Vec<Element>,
+    actual: &Vec<Element>,
+    offset: usize,
+    path: &str,
+    options: &CompareOptions,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    for (index, expected_element) in expected.iter().enumerate() {
+        let element_path = format!("{path}/{}", offset + index);
+        match actual.get(index) {
+            Some(actual_element) => match_body_element(
+                expected_element,
+                actual_element,
+                &element_path,
+                options,
+                mismatches,
+            ),
+            None => mismatches.push(Mismatch::new(
+                element_path,
+                describe_element(expected_element),
+                String::new(),
+                MismatchReason::Missing,
+            )),
         }
     }
-
-    return true;
 }
 
-fn match_array_contains(expected: &Vec<Element>, actual: &Vec<Element>) -> bool {
+fn match_array_contains(
+    expected: &Vec<Element>,
+    actual: &Vec<Element>,
+    options: &CompareOptions,
+) -> bool {
+    if expected.len() > actual.len() {
+        return false;
+    }
+
     for index in 0..(actual.len() - expected.len() + 1) {
-        let matched = match_array_literal(
+        let mut window_mismatches = Vec::new();
+        match_array_literal(
             expected,
             &actual
                 .clone()
@@ -250,15 +508,88 @@ fn match_array_contains(expected: &Vec<Element>, actual: &Vec<Element>) -> bool
                 .skip(index)
                 .take(expected.len())
                 .collect_vec(),
+            index,
+            "",
+            options,
+            &mut window_mismatches,
         );
-        if matched {
+        if window_mismatches.is_empty() {
             return true;
         }
     }
     return false;
 }
 
-fn match_body_number(expected: &Number, actual: &Number) -> bool {
+fn count_assigned(expected: &[Element], actual: &[Element], options: &CompareOptions) -> usize {
+    return assign_elements(expected, actual, options)
+        .iter()
+        .flatten()
+        .count();
+}
+
+/// Assigns each expected element a different matching actual element, if possible,
+/// and returns the index of the assigned actual element for each expected element
+pub(crate) fn assign_elements(
+    expected: &[Element],
+    actual: &[Element],
+    options: &CompareOptions,
+) -> Vec<Option<usize>> {
+    let candidates = expected
+        .iter()
+        .map(|expected_element| {
+            actual
+                .iter()
+                .positions(|actual_element| {
+                    element_matches(expected_element, actual_element, options)
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    // Earlier assignments are moved along augmenting paths when their element is needed
+    let mut assigned_to = vec![None; actual.len()];
+    for index in 0..expected.len() {
+        let mut visited = vec![false; actual.len()];
+        try_assign(index, &candidates, &mut assigned_to, &mut visited);
+    }
+
+    let mut assignment = vec![None; expected.len()];
+    for (actual_index, expected_index) in assigned_to.into_iter().enumerate() {
+        if let Some(expected_index) = expected_index {
+            assignment[expected_index] = Some(actual_index);
+        }
+    }
+    return assignment;
+}
+
+fn try_assign(
+    index: usize,
+    candidates: &[Vec<usize>],
+    assigned_to: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for &candidate in &candidates[index] {
+        if visited[candidate] {
+            continue;
+        }
+        visited[candidate] = true;
+        let available = match assigned_to[candidate] {
+            Some(other) => try_assign(other, candidates, assigned_to, visited),
+            None => true,
+        };
+        if available {
+            assigned_to[candidate] = Some(index);
+            return true;
+        }
+    }
+    return false;
+}
+
+fn match_body_number(expected: &Number, actual: &Number, options: &CompareOptions) -> bool {
+    if options.numeric_equality {
+        return numbers_equal(expected.as_f64(), actual.as_f64());
+    }
+
     return match (expected, actual) {
         (Number::Int(expected_int), Number::Int(actual_int)) => expected_int == actual_int,
         (Number::Fraction(expected_faction), Number::Fraction(actual_fraction)) => {
@@ -271,39 +602,99 @@ fn match_body_number(expected: &Number, actual: &Number) -> bool {
     };
 }
 
-fn log_header_mismatch(snapshot_header: &Header, response_header: Option<&Header>) {
-    log::error!(
-        "Header named: {:?} did NOT match snapshot",
-        snapshot_header.name
-    );
+fn match_body_numeric(comparison: &NumericComparison, actual: &Value) -> bool {
+    let Value::Number(actual) = actual else {
+        return false;
+    };
+    let actual = actual.as_f64();
+    return match comparison {
+        NumericComparison::Range(min, max) => min.as_f64() <= actual && actual <= max.as_f64(),
+        NumericComparison::Approx(expected, tolerance) => {
+            (actual - expected.as_f64()).abs() <= tolerance.as_f64()
+        }
+        NumericComparison::GreaterThan(bound) => actual > bound.as_f64(),
+        NumericComparison::GreaterOrEqual(bound) => actual >= bound.as_f64(),
+        NumericComparison::LessThan(bound) => actual < bound.as_f64(),
+        NumericComparison::LessOrEqual(bound) => actual <= bound.as_f64(),
+    };
+}
 
-    let response_value = response_header
-        .map(|header| header.value.to_string())
-        .unwrap_or_default();
-
-    match &snapshot_header.comparison {
-        Some(Comparison::Exact) => {
-            log::error!(
-                "Expected: {:?} but got {:?}",
-                snapshot_header.value.to_string(),
-                response_value
-            );
-        }
-        Some(Comparison::TimestampFormat(pattern)) => {
-            log::error!(
-                "Timestamp {:?} does not match pattern {}",
-                response_value,
-                pattern.to_string()
-            );
-        }
-        Some(Comparison::Guid) => {
-            log::error!("Expected a guid but got {:?}", response_value);
-        }
-        _ => panic!(
-            "Comparison type {:?} not supported for headers",
-            &snapshot_header.comparison
-        ),
-    }
+// Numbers that differ only in the last digit of their representation, like
+// 0.30000000000000004 and 0.3, are considered equal
+fn numbers_equal(expected: f64, actual: f64) -> bool {
+    return (expected - actual).abs() <= f64::EPSILON * expected.abs().max(actual.abs());
+}
+
+// Builds a JSON pointer by escaping the key as described in RFC 6901
+fn pointer(path: &str, key: &str) -> String {
+    return format!("{path}/{}", key.replace('~', "~0").replace('/', "~1"));
+}
+
+fn describe_header(header: &Header) -> String {
+    return match &header.comparison {
+        Some(Comparison::Exact) | None => header.value.to_string(),
+        Some(comparison) => describe_comparison(comparison, &Value::from(header.value.to_string())),
+    };
+}
+
+fn describe_body(body: &ResponseBody) -> String {
+    return match body {
+        ResponseBody::Json(json) => describe_element(&json.element),
+        ResponseBody::Text(text) => text.trim_end().to_string(),
+        ResponseBody::Binary { size, sha256 } => format!("{size} bytes with sha256 {sha256}"),
+    };
+}
+
+fn describe_element(element: &Element) -> String {
+    return match &element.comparison {
+        Some(Comparison::Exact) | None => describe_value(&element.value),
+        Some(comparison) => describe_comparison(comparison, &element.value),
+    };
+}
+
+fn describe_comparison(comparison: &Comparison, value: &Value) -> String {
+    return match comparison {
+        Comparison::TimestampFormat(pattern) => format!("timestamp(\"{pattern}\")"),
+        Comparison::Guid => String::from("guid"),
+        Comparison::Regex(pattern) => format!("regex(\"{pattern}\")"),
+        Comparison::Type(value_type) => value_type.to_string(),
+        Comparison::Numeric(comparison) => comparison.to_string(),
+        Comparison::Ignore => String::from("_"),
+        Comparison::Exact => describe_value(value),
+    };
+}
+
+fn describe_value(value: &Value) -> String {
+    return match value {
+        Value::VariableReference(name) => format!("{{{{{name}}}}}"),
+        Value::Object(object) => {
+            let members = object
+                .members
+                .iter()
+                .map(|member| format!("\"{}\": {}", member.key, describe_element(&member.value)))
+                .chain(object.partial.then(|| String::from("...")))
+                .join(", ");
+            format!("{{{members}}}")
+        }
+        Value::Array(array) => describe_array(array),
+        Value::String(composite) => serde_json::to_string(&composite.to_string()).unwrap(),
+        Value::Number(number) => number.to_string(),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Null() => String::from("null"),
+    };
+}
+
+fn describe_array(array: &Array) -> String {
+    let describe = |elements: &Vec<Element>| elements.iter().map(describe_element).join(", ");
+    return match array {
+        Array::VariableReference(name) => format!("{{{{{name}}}}}"),
+        Array::Literal(elements) => format!("[{}]", describe(elements)),
+        Array::StartsWith(elements) => format!("[{}, ...]", describe(elements)),
+        Array::Contains(elements) => format!("[..., {}, ...]", describe(elements)),
+        Array::EndsWith(elements) => format!("[..., {}]", describe(elements)),
+        Array::Unordered(elements) => format!("[~ {} ~]", describe(elements)),
+        Array::ContainsAll(elements) => format!("[~ {}, ... ~]", describe(elements)),
+    };
 }
 
 fn compare_timestamp_format(pattern: &CompositeString, value: &str) -> bool {
@@ -323,3 +714,7 @@ fn compare_guid_format(value: &str) -> bool {
         return false;
     }
 }
+
+fn compare_regex(pattern: &str, value: &str) -> bool {
+    return Regex::new(pattern).is_ok_and(|regex| regex.is_match(value));
+}
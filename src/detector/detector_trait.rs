@@ -1,6 +1,6 @@
 ﻿use crate::types::{Element, Header};
 
-pub trait Detector {
+pub trait Detector: Send + Sync {
     fn detect_in_header(&self, header: &Header) -> Option<Header>;
     fn detect_in_element(&self, element: &Element) -> Option<Element>;
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 ﻿use crate::types::{
-    Array, Detector, Element, Header, Json, Member, Object, SnapResponse, UpdateOptions, Value,
+    Comparison, Detector, Element, Header, Json, Member, Object, ResponseBody, SnapResponse,
+    UpdateOptions, Value,
 };
 use std::collections::{HashMap};
 
@@ -37,7 +38,10 @@ impl Replacer {
         return SnapResponse {
             status: response.status,
             headers: self.detect_in_headers(response.headers),
-            body: self.detect_in_json(response.body),
+            body: response.body.map(|body| match body {
+                ResponseBody::Json(json) => ResponseBody::Json(self.detect_in_json(json)),
+                body => body,
+            }),
         };
     }
 
@@ -50,6 +54,10 @@ impl Replacer {
     }
 
     fn detect_in_header(&self, header: &Header) -> Header {
+        if has_comparison(&header.comparison) {
+            return header.clone();
+        }
+
         for detector in &self.detectors {
             if let Some(detected) = detector.detect_in_header(header) {
                 return detected;
@@ -59,16 +67,17 @@ impl Replacer {
         return header.clone();
     }
 
-    fn detect_in_json(&self, json: Option<Json>) -> Option<Json> {
-        if let Some(json) = json {
-            return Some(Json {
-                element: self.detect_in_element(json.element),
-            });
-        }
-        return None;
+    fn detect_in_json(&self, json: Json) -> Json {
+        return Json {
+            element: self.detect_in_element(json.element),
+        };
     }
 
     fn detect_in_element(&self, element: Element) -> Element {
+        if has_comparison(&element.comparison) {
+            return element;
+        }
+
         for detector in &self.detectors {
             if let Some(detected) = detector.detect_in_element(&element) {
                 return detected;
@@ -84,19 +93,25 @@ impl Replacer {
 
     fn detect_in_value(&self, value: Value) -> Value {
         return match value {
-            Value::Array(array) => {
-                let mut elements = Vec::new();
-                for element in array.get_known_elements() {
-                    elements.push(self.detect_in_element(element));
+            Value::Array(array) => match array.get_known_elements() {
+                Ok(known_elements) => {
+                    let mut elements = Vec::new();
+                    for element in known_elements {
+                        elements.push(self.detect_in_element(element));
+                    }
+                    Value::Array(array.with_elements(elements))
                 }
-                Value::Array(Array::Literal(elements))
-            }
+                Err(_) => Value::Array(array),
+            },
             Value::Object(object) => {
                 let mut members = Vec::new();
                 for member in object.members {
                     members.push(self.detect_in_member(member))
                 }
-                Value::Object(Object { members })
+                Value::Object(Object {
+                    members,
+                    partial: object.partial,
+                })
             }
             _ => value,
         };
@@ -110,5 +125,7 @@ impl Replacer {
     }
 }
 
-
-
+// Comparisons kept from the previous snapshot are not replaced by detected ones
+fn has_comparison(comparison: &Option<Comparison>) -> bool {
+    return !matches!(comparison, None | Some(Comparison::Exact));
+}
@@ -0,0 +1,178 @@
+use chumsky::error::{Simple, SimpleReason};
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub expected: Vec<String>,
+    pub snippet: String,
+    pub underline_length: usize,
+}
+
+impl ParseError {
+    /// Maps chumsky errors from parsing `source` back to `path`.
+    /// `line_offset` is the zero-based line in `path` where `source` starts, and
+    /// `column_offset` the indentation that was trimmed from its first line.
+    pub fn new(
+        path: &Path,
+        line_offset: usize,
+        column_offset: usize,
+        source: &str,
+        errors: Vec<Simple<char>>,
+    ) -> Self {
+        let diagnostics = errors
+            .into_iter()
+            .map(|error| Diagnostic::new(path, line_offset, column_offset, source, error))
+            .collect();
+        return Self { diagnostics };
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reports = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .join("\n\n");
+        write!(f, "{}", reports)
+    }
+}
+
+impl Diagnostic {
+    fn new(
+        path: &Path,
+        line_offset: usize,
+        column_offset: usize,
+        source: &str,
+        error: Simple<char>,
+    ) -> Self {
+        let span = error.span();
+        let (line_index, mut column_index) = find_position(source, span.start);
+        let mut snippet = source.lines().nth(line_index).unwrap_or("").to_string();
+        if line_index == 0 {
+            column_index += column_offset;
+            snippet = format!("{}{snippet}", " ".repeat(column_offset));
+        }
+        let remaining_on_line = snippet.chars().count().saturating_sub(column_index);
+        let underline_length = span
+            .end
+            .saturating_sub(span.start)
+            .min(remaining_on_line)
+            .max(1);
+
+        let found = match error.found() {
+            Some(c) => format!("unexpected {}", describe_char(c)),
+            None => String::from("unexpected end of input"),
+        };
+        let message = match error.reason() {
+            SimpleReason::Unexpected => found,
+            SimpleReason::Unclosed { delimiter, .. } => {
+                format!("unclosed delimiter {}, {found}", describe_char(delimiter))
+            }
+            SimpleReason::Custom(message) => message.clone(),
+        };
+        let message = match error.label() {
+            Some(label) => format!("{message} while parsing {label}"),
+            None => message,
+        };
+
+        let expected = error
+            .expected()
+            .map(|expected| match expected {
+                Some(c) => describe_char(c),
+                None => String::from("end of input"),
+            })
+            .sorted()
+            .dedup()
+            .collect();
+
+        return Self {
+            path: path.to_path_buf(),
+            line: line_offset + line_index + 1,
+            column: column_index + 1,
+            message,
+            expected,
+            snippet,
+            underline_length,
+        };
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let indent = self
+            .snippet
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{gutter}--> {}:{}:{}",
+            self.path.display(),
+            self.line,
+            self.column
+        )?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(
+            f,
+            "{gutter} | {indent}{}",
+            "^".repeat(self.underline_length)
+        )?;
+        if !self.expected.is_empty() {
+            write!(
+                f,
+                "\n{gutter} = expected one of {}",
+                self.expected.join(", ")
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+// Chumsky spans are char indices, so the position is found by walking chars
+fn find_position(source: &str, char_index: usize) -> (usize, usize) {
+    let mut line = 0;
+    let mut column = 0;
+    for c in source.chars().take(char_index) {
+        if c == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += 1;
+        }
+    }
+    return (line, column);
+}
+
+fn describe_char(c: &char) -> String {
+    return match c {
+        '\n' => String::from("newline"),
+        '\r' => String::from("carriage return"),
+        '\t' => String::from("tab"),
+        ' ' => String::from("space"),
+        other => format!("'{other}'"),
+    };
+}
+
+/// Counts the lines before `byte_index`, giving the zero-based line it is on.
+pub(crate) fn line_of(text: &str, byte_index: usize) -> usize {
+    return text[..byte_index].matches('\n').count();
+}
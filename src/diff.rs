@@ -0,0 +1,208 @@
+use crate::comparer;
+use crate::merger;
+use crate::types::{
+    Array, CompareOptions, Element, Json, Member, Number, Object, ResponseBody, SnapResponse,
+    Snapshot, SnapshotDiff, Status, Value,
+};
+use similar::{ChangeTag, TextDiff};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Formats the snapshot and the response for diffing. Parts of the response that satisfy
+/// a comparison like `{{_:guid}}` or an array pattern are written as in the snapshot,
+/// so only the real differences show up in the diff.
+pub fn create_snapshot_diff(
+    snapshot: Option<&Snapshot>,
+    response: &SnapResponse,
+    options: &CompareOptions,
+) -> SnapshotDiff {
+    return match snapshot {
+        Some(snapshot) => SnapshotDiff {
+            expected: merger::format_expected_snapshot(snapshot),
+            actual: merger::format_expected_snapshot(&annotate_response(
+                snapshot, response, options,
+            )),
+        },
+        None => SnapshotDiff {
+            expected: String::new(),
+            actual: merger::format_snapshot(response),
+        },
+    };
+}
+
+/// Builds the response to write as the updated snapshot. Parts that still satisfy the
+/// snapshot keep their comparisons and array patterns instead of the literal response values.
+pub(crate) fn keep_matching_parts(
+    snapshot: &Snapshot,
+    response: &SnapResponse,
+    options: &CompareOptions,
+) -> SnapResponse {
+    let annotated = annotate_response(snapshot, response, options);
+    return SnapResponse {
+        status: response.status,
+        headers: annotated
+            .headers
+            .into_iter()
+            .map(|header| (header.name.clone(), header))
+            .collect(),
+        body: annotated.body,
+    };
+}
+
+/// Renders the diff in unified format, coloring removed, added and hunk header lines
+pub fn render_unified_diff(diff: &SnapshotDiff, color: bool) -> String {
+    let paint = |code: &str, line: String| {
+        if color {
+            format!("{code}{line}{RESET}")
+        } else {
+            line
+        }
+    };
+
+    let text_diff = TextDiff::from_lines(&diff.expected, &diff.actual);
+    let mut lines = vec![
+        paint(RED, String::from("--- expected")),
+        paint(GREEN, String::from("+++ actual")),
+    ];
+    for hunk in text_diff.unified_diff().context_radius(3).iter_hunks() {
+        lines.push(paint(CYAN, hunk.header().to_string()));
+        for change in hunk.iter_changes() {
+            let line = change.value().trim_end_matches('\n');
+            lines.push(match change.tag() {
+                ChangeTag::Delete => paint(RED, format!("-{line}")),
+                ChangeTag::Insert => paint(GREEN, format!("+{line}")),
+                ChangeTag::Equal => format!(" {line}"),
+            });
+        }
+    }
+    return lines.join("\n");
+}
+
+fn annotate_response(
+    snapshot: &Snapshot,
+    response: &SnapResponse,
+    options: &CompareOptions,
+) -> Snapshot {
+    let status = if comparer::status_matches(&snapshot.status, &response.status) {
+        snapshot.status.clone()
+    } else {
+        Status::Value(Number::Int(response.status as i64))
+    };
+
+    let headers = response
+        .headers
+        .values()
+        .map(|header| {
+            let expected = snapshot.headers.iter().find(|h| h.name == header.name);
+            return match expected {
+                Some(expected) if comparer::header_matches(expected, header) => expected.clone(),
+                _ => header.clone(),
+            };
+        })
+        .collect();
+
+    let body = match (&snapshot.body, &response.body) {
+        (Some(ResponseBody::Json(expected)), Some(ResponseBody::Json(actual))) => {
+            Some(ResponseBody::Json(Json {
+                element: annotate_element(&expected.element, &actual.element, options),
+            }))
+        }
+        _ => response.body.clone(),
+    };
+
+    return Snapshot {
+        status,
+        headers,
+        body,
+        body_file: snapshot.body_file.clone(),
+    };
+}
+
+fn annotate_element(expected: &Element, actual: &Element, options: &CompareOptions) -> Element {
+    if comparer::element_matches(expected, actual, options) {
+        return expected.clone();
+    }
+
+    let value = match (&expected.value, &actual.value) {
+        (Value::Object(expected_object), Value::Object(actual_object)) => {
+            Value::Object(annotate_object(expected_object, actual_object, options))
+        }
+        (Value::Array(Array::Literal(expected_elements)), Value::Array(actual_array)) => {
+            let Ok(actual_elements) = actual_array.get_known_elements() else {
+                return actual.clone();
+            };
+            let elements = actual_elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| match expected_elements.get(index) {
+                    Some(expected_element) => annotate_element(expected_element, element, options),
+                    None => element.clone(),
+                })
+                .collect();
+            Value::Array(Array::Literal(elements))
+        }
+        (
+            Value::Array(
+                expected_array @ (Array::Unordered(expected_elements)
+                | Array::ContainsAll(expected_elements)),
+            ),
+            Value::Array(actual_array),
+        ) => {
+            let Ok(actual_elements) = actual_array.get_known_elements() else {
+                return actual.clone();
+            };
+            // The order does not matter, so any element that still matches is kept
+            let mut unused = expected_elements.iter().collect::<Vec<_>>();
+            let elements = actual_elements
+                .into_iter()
+                .map(|element| {
+                    let matching = unused.iter().position(|expected| {
+                        return comparer::element_matches(expected, &element, options);
+                    });
+                    return match matching {
+                        Some(position) => unused.remove(position).clone(),
+                        None => element,
+                    };
+                })
+                .collect();
+            Value::Array(expected_array.with_elements(elements))
+        }
+        _ => return actual.clone(),
+    };
+
+    return Element {
+        value,
+        variable_store: actual.variable_store.clone(),
+        comparison: actual.comparison.clone(),
+    };
+}
+
+// Members are kept in the order of the snapshot to avoid noise from reordering.
+// Members that a partial snapshot leaves out are not shown.
+fn annotate_object(expected: &Object, actual: &Object, options: &CompareOptions) -> Object {
+    let find = |members: &Vec<Member>, key: &String| {
+        return members.iter().find(|member| &member.key == key).cloned();
+    };
+
+    let mut members = Vec::new();
+    for expected_member in &expected.members {
+        if let Some(actual_member) = find(&actual.members, &expected_member.key) {
+            members.push(Member {
+                key: actual_member.key,
+                value: annotate_element(&expected_member.value, &actual_member.value, options),
+            });
+        }
+    }
+    for actual_member in &actual.members {
+        if !expected.partial && find(&expected.members, &actual_member.key).is_none() {
+            members.push(actual_member.clone());
+        }
+    }
+    return Object {
+        members,
+        partial: expected.partial,
+    };
+}
@@ -0,0 +1,74 @@
+use crate::error::HttpSnapError;
+use crate::parser;
+use crate::types::Value;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// Environment file that is used when an environment is selected without a path
+pub const ENVIRONMENT_FILE: &str = "http-snap.env.json";
+
+/// Block with the variables that every environment shares
+const SHARED: &str = "$shared";
+
+type Environments = BTreeMap<String, HashMap<String, Value>>;
+
+/// Loads an environment from a JSON file with one block per environment, like
+/// `{ "$shared": { ... }, "staging": { ... } }`. A private file next to it, with
+/// `.private.json` as extension, is merged over it to keep secrets out of the repository.
+/// Variables of the selected environment take precedence over the shared ones
+pub fn load_named_environment(
+    path: &PathBuf,
+    name: &str,
+) -> Result<HashMap<String, Value>, HttpSnapError> {
+    let mut environments = read_environments(path)?;
+    let private_path = get_private_path(path);
+    if private_path.exists() {
+        for (environment, variables) in read_environments(&private_path)? {
+            environments
+                .entry(environment)
+                .or_default()
+                .extend(variables);
+        }
+    }
+
+    let Some(selected) = environments.remove(name) else {
+        let available = environments.keys().filter(|key| *key != SHARED).join(", ");
+        return Err(HttpSnapError::InvalidEnvironment {
+            path: path.clone(),
+            reason: format!("there is no environment named \"{name}\", found: {available}"),
+        });
+    };
+    let mut variables = environments.remove(SHARED).unwrap_or_default();
+    variables.extend(selected);
+    return Ok(variables);
+}
+
+fn get_private_path(path: &PathBuf) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    return path.with_file_name(format!("{stem}.private.json"));
+}
+
+fn read_environments(path: &PathBuf) -> Result<Environments, HttpSnapError> {
+    let to_error = |reason: String| HttpSnapError::InvalidEnvironment {
+        path: path.clone(),
+        reason,
+    };
+    let content = std::fs::read_to_string(path).map_err(|error| HttpSnapError::io(path, error))?;
+    let json: BTreeMap<String, BTreeMap<String, serde_json::Value>> =
+        serde_json::from_str(content.trim_start_matches('\u{feff}'))
+            .map_err(|error| to_error(error.to_string()))?;
+
+    let mut environments = Environments::new();
+    for (environment, members) in json {
+        let mut variables = HashMap::new();
+        for (name, value) in members {
+            // Parsed like a request body, so strings can refer to other variables
+            let json = parser::parse_json(&value.to_string())
+                .ok_or_else(|| to_error(format!("the value of \"{name}\" is not supported")))?;
+            variables.insert(name, json.element.value);
+        }
+        environments.insert(environment, variables);
+    }
+    return Ok(environments);
+}
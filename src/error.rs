@@ -0,0 +1,116 @@
+use crate::diagnostics::ParseError;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum HttpSnapError {
+    Parse(ParseError),
+    UnknownVariable(String),
+    InvalidVariable {
+        name: String,
+        reason: String,
+    },
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    InvalidEnvironment {
+        path: PathBuf,
+        reason: String,
+    },
+    UnsupportedFile(PathBuf),
+    InvalidPath {
+        path: PathBuf,
+        reason: String,
+    },
+    InvalidHeader {
+        name: String,
+        reason: String,
+    },
+    InvalidBody(String),
+    InvalidUrl {
+        url: String,
+        reason: String,
+    },
+    Transport(reqwest::Error),
+    SnapshotWrite {
+        path: PathBuf,
+        reason: String,
+    },
+    Server(std::io::Error),
+}
+
+impl HttpSnapError {
+    pub(crate) fn io(path: &PathBuf, source: std::io::Error) -> Self {
+        return HttpSnapError::Io {
+            path: path.clone(),
+            source,
+        };
+    }
+}
+
+impl Display for HttpSnapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HttpSnapError::Parse(error) => write!(f, "{error}"),
+            HttpSnapError::UnknownVariable(name) => {
+                write!(f, "Variable named \"{name}\" was not found")
+            }
+            HttpSnapError::InvalidVariable { name, reason } => {
+                write!(f, "Variable named \"{name}\" {reason}")
+            }
+            HttpSnapError::Io { path, source } => {
+                write!(f, "Could not read {}: {source}", path.display())
+            }
+            HttpSnapError::InvalidEnvironment { path, reason } => {
+                write!(f, "Invalid environment file {}: {reason}", path.display())
+            }
+            HttpSnapError::UnsupportedFile(path) => {
+                write!(f, "Unsupported file type {}", path.display())
+            }
+            HttpSnapError::InvalidPath { path, reason } => {
+                write!(f, "Invalid path {}: {reason}", path.display())
+            }
+            HttpSnapError::InvalidHeader { name, reason } => {
+                write!(f, "Header named \"{name}\" is invalid: {reason}")
+            }
+            HttpSnapError::InvalidBody(reason) => write!(f, "Invalid body: {reason}"),
+            HttpSnapError::InvalidUrl { url, reason } => {
+                write!(f, "Url \"{url}\" is invalid: {reason}")
+            }
+            HttpSnapError::Transport(error) => write!(f, "Request failed: {error}"),
+            HttpSnapError::SnapshotWrite { path, reason } => {
+                write!(
+                    f,
+                    "Unable to write snapshot to {}: {reason}",
+                    path.display()
+                )
+            }
+            HttpSnapError::Server(error) => write!(f, "Mock server failed: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for HttpSnapError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            HttpSnapError::Parse(error) => Some(error),
+            HttpSnapError::Io { source, .. } => Some(source),
+            HttpSnapError::Transport(error) => Some(error),
+            HttpSnapError::Server(error) => Some(error),
+            _ => None,
+        };
+    }
+}
+
+impl From<ParseError> for HttpSnapError {
+    fn from(error: ParseError) -> Self {
+        return HttpSnapError::Parse(error);
+    }
+}
+
+impl From<reqwest::Error> for HttpSnapError {
+    fn from(error: reqwest::Error) -> Self {
+        return HttpSnapError::Transport(error);
+    }
+}
@@ -1,22 +1,35 @@
 ﻿use crate::client::HttpResponse;
+use crate::diagnostics::ParseError;
+use crate::error::HttpSnapError;
 use crate::types::{
-    ClientOptions, ExecuteOptions, ExecutedRequest, HttpFile, Mode, RawInput, UpdateOptions,
+    ClientOptions, CompareOptions, ExecuteOptions, ExecutedRequest, FileReport, HttpFile, Mismatch,
+    MismatchReason, Mode, PartContent, RawInput, RequestBody, RequestReport, ResponseBody,
+    SnapResponse, UpdateOptions,
 };
+use crate::variable_generator::VariableGenerator;
 use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
 use pulldown_cmark_to_cmark::cmark;
+use reqwest::header::HeaderMap;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{read_to_string, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{time};
 
 pub mod client;
 pub mod comparer;
 pub mod detector;
+pub mod diagnostics;
+pub mod diff;
+pub mod environment;
+pub mod error;
 pub mod merger;
 pub mod parser;
+pub mod recorder;
+pub mod report;
 pub mod request_extractor;
+pub mod server;
 pub mod types;
 pub mod variable_generator;
 pub mod variable_store;
@@ -26,37 +39,39 @@ pub async fn run(
     environment_variables: &HashMap<String, types::Value>,
     execute_options: &ExecuteOptions,
     client_options: &ClientOptions,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let extension = path_to_file.extension().unwrap();
+) -> Result<FileReport, HttpSnapError> {
+    let extension = path_to_file.extension().unwrap_or_default();
     if extension == "http" {
-        let (passed, file_content) = handle_http_file(
+        let (requests, file_content) = handle_http_file(
             path_to_file,
             environment_variables,
             execute_options,
             client_options,
         )
         .await?;
-        if !passed && execute_options.mode == Mode::Update {
-            let mut file = File::options()
-                .write(true)
-                .truncate(true)
-                .open(path_to_file)?;
-
-            file.write_all(&file_content.as_bytes())
-                .expect("Unable to write snapshot");
-            file.flush()?;
+        let report = FileReport {
+            path: path_to_file.clone(),
+            requests,
+        };
+        if !report.passed() && execute_options.mode == Mode::Update {
+            write_snapshot(path_to_file, &file_content)?;
         }
-        return Ok(passed);
+        return Ok(report);
     } else if extension == "md" {
-        let (passed, sections_content) = handle_markdown_file(
+        let (requests, sections_content) = handle_markdown_file(
             path_to_file,
             environment_variables,
             execute_options,
             client_options,
         )
         .await?;
-        if !passed && execute_options.mode == Mode::Update {
-            let raw_input = read_to_string(path_to_file).unwrap();
+        let report = FileReport {
+            path: path_to_file.clone(),
+            requests,
+        };
+        if !report.passed() && execute_options.mode == Mode::Update {
+            let raw_input = read_to_string(path_to_file)
+                .map_err(|error| HttpSnapError::io(path_to_file, error))?;
             let parser = Parser::new(&raw_input);
 
             let mut index = 0;
@@ -87,35 +102,49 @@ pub async fn run(
                 other => other,
             });
             let mut content = String::new();
-            cmark(mapped, &mut content).expect("Markdown re-emit failed");
+            cmark(mapped, &mut content).map_err(|error| HttpSnapError::SnapshotWrite {
+                path: path_to_file.clone(),
+                reason: format!("Markdown re-emit failed: {error}"),
+            })?;
 
-            let mut file = File::options()
-                .write(true)
-                .truncate(true)
-                .open(path_to_file)?;
-
-            file.write_all(&content.as_bytes())
-                .expect("Unable to write snapshot");
-            file.flush()?;
+            write_snapshot(path_to_file, &content)?;
         }
-        return Ok(passed);
+        return Ok(report);
     } else {
-        panic!("Unknown file format")
+        return Err(HttpSnapError::UnsupportedFile(path_to_file.clone()));
     }
 }
 
+fn write_snapshot(path_to_file: &PathBuf, content: &str) -> Result<(), HttpSnapError> {
+    let to_error = |error: std::io::Error| HttpSnapError::SnapshotWrite {
+        path: path_to_file.clone(),
+        reason: error.to_string(),
+    };
+    let mut file = File::options()
+        .write(true)
+        .truncate(true)
+        .open(path_to_file)
+        .map_err(to_error)?;
+
+    file.write_all(&content.as_bytes()).map_err(to_error)?;
+    file.flush().map_err(to_error)?;
+    return Ok(());
+}
+
 async fn handle_markdown_file(
     path_to_file: &PathBuf,
     environment_variables: &HashMap<String, types::Value>,
     execute_options: &ExecuteOptions,
     client_options: &ClientOptions,
-) -> Result<(bool, Vec<String>), Box<dyn std::error::Error>> {
-    let requests = request_extractor::extract_requests(path_to_file);
+) -> Result<(Vec<RequestReport>, Vec<String>), HttpSnapError> {
+    let requests = request_extractor::extract_requests(path_to_file)?;
     let stop_on_failure = get_stop_on_failure_option(&execute_options);
-    let (passed, raw_snapshots) = run_requests(
+    let (reports, raw_snapshots) = run_requests(
         requests,
         environment_variables,
         stop_on_failure,
+        &execute_options.compare_options,
+        VariableGenerator::for_file(execute_options.seed, path_to_file),
         client_options,
     )
     .await?;
@@ -151,9 +180,9 @@ async fn handle_markdown_file(
         sections_content.push(create_http_content(
             &section,
             &execute_options.update_options,
-        ))
+        )?)
     }
-    return Ok((passed, sections_content));
+    return Ok((reports, sections_content));
 }
 
 async fn handle_http_file(
@@ -161,28 +190,32 @@ async fn handle_http_file(
     environment_variables: &HashMap<String, types::Value>,
     execute_options: &ExecuteOptions,
     client_options: &ClientOptions,
-) -> Result<(bool, String), Box<dyn std::error::Error>> {
-    let requests = request_extractor::extract_requests(path_to_file);
+) -> Result<(Vec<RequestReport>, String), HttpSnapError> {
+    let requests = request_extractor::extract_requests(path_to_file)?;
     let stop_on_failure = get_stop_on_failure_option(&execute_options);
-    let (passed, raw_snapshots) = run_requests(
+    let (reports, raw_snapshots) = run_requests(
         requests,
         environment_variables,
         stop_on_failure,
+        &execute_options.compare_options,
+        VariableGenerator::for_file(execute_options.seed, path_to_file),
         client_options,
     )
     .await?;
     let final_snapshots = detect_patterns(raw_snapshots, &execute_options.update_options);
-    let file_content = create_http_content(&final_snapshots, &execute_options.update_options);
-    return Ok((passed, file_content));
+    let file_content = create_http_content(&final_snapshots, &execute_options.update_options)?;
+    return Ok((reports, file_content));
 }
 
 async fn run_requests(
     inputs: Vec<RawInput>,
     environment_variables: &HashMap<String, types::Value>,
     stop_on_failure: bool,
+    compare_options: &CompareOptions,
+    generator: VariableGenerator,
     client_options: &ClientOptions,
-) -> Result<(bool, Vec<ExecutedRequest>), Box<dyn std::error::Error>> {
-    let mut passed = true;
+) -> Result<(Vec<RequestReport>, Vec<ExecutedRequest>), HttpSnapError> {
+    let mut reports = Vec::new();
     let mut executed_requests = Vec::new();
     for input in &inputs {
         executed_requests.push(ExecutedRequest {
@@ -191,48 +224,143 @@ async fn run_requests(
         });
     }
 
-    let mut variable_store = variable_store::VariableStore::new();
-    variable_store.extend_variables(&environment_variables);
+    let mut variable_store = variable_store::VariableStore::with_generator(generator);
+    variable_store.extend_variables(&environment_variables)?;
 
     let client = client::HttpClient::new(client_options);
     for (index, request) in inputs.into_iter().enumerate() {
-        let delay_section = parser::try_parse_delay(&request.text).unwrap();
+        let delay_section = parser::try_parse_delay(&request.text)
+            .map_err(|errors| to_parse_error(&request, errors))?;
         if let Some(delay) = delay_section {
             tokio::time::sleep(time::Duration::from_millis(delay)).await;
             continue;
         }
 
-        let http_file = parser::parse_file(&request.text).unwrap();
-        let http_file_without_variables = variable_store.replace_variables(http_file);
+        let http_file =
+            parser::parse_file(&request.text).map_err(|errors| to_parse_error(&request, errors))?;
+        let http_file = parser::load_file_references(http_file, &request.path)?;
+        let http_file_without_variables = variable_store.replace_variables(http_file)?;
         log_variable_store(&variable_store);
 
         log_request(&http_file_without_variables);
+        let started = time::Instant::now();
         let response = client.send_request(&http_file_without_variables).await?;
         log_response(&response);
 
         let parsed_response = parser::parse_response(&response).await?;
 
-        let mut matched_option = false;
+        let mut matched_option = None;
+        let mut captured_variables = BTreeMap::new();
+        let mut closest: Option<(usize, Vec<Mismatch>)> = None;
+        let mut captures_missing = false;
         for (option_index, snapshot) in http_file_without_variables.snapshots.iter().enumerate() {
-            matched_option = comparer::compare_to_snapshot(&snapshot, &parsed_response);
-            if matched_option {
+            let mismatches =
+                comparer::compare_to_snapshot(&snapshot, &parsed_response, compare_options);
+            if mismatches.is_empty() {
                 log_option_match(index, option_index);
-                variable_store.update_variables(&snapshot, &parsed_response);
+                captured_variables =
+                    variable_store.update_variables(&snapshot, &parsed_response, compare_options);
+                let captures = &http_file_without_variables.captures;
+                let (captured, missing) =
+                    variable_store.capture_variables(captures, &parsed_response);
+                captured_variables.extend(captured);
+                if missing.is_empty() {
+                    matched_option = Some(option_index);
+                } else {
+                    captures_missing = true;
+                    closest = Some((option_index, missing));
+                }
                 break;
             }
+            if closest
+                .as_ref()
+                .map_or(true, |(_, closest)| mismatches.len() < closest.len())
+            {
+                closest = Some((option_index, mismatches));
+            }
         }
 
-        if !matched_option {
-            passed = false;
-            log::error!("Snapshot {0} did NOT match", index + 1);
-            executed_requests[index].snapshot = Some(parsed_response);
-            if stop_on_failure {
+        let snapshots = &http_file_without_variables.snapshots;
+        let closest_option = closest.as_ref().map(|(option_index, _)| *option_index);
+        let (mismatches, diff) = match (matched_option, closest) {
+            (Some(_), _) => (Vec::new(), None),
+            (None, Some((option_index, closest))) => {
+                let snapshot = Some(&snapshots[option_index]);
+                (
+                    closest,
+                    Some(diff::create_snapshot_diff(
+                        snapshot,
+                        &parsed_response,
+                        compare_options,
+                    )),
+                )
+            }
+            (None, None) => (
+                vec![Mismatch::new(
+                    String::new(),
+                    String::from("SNAPSHOT"),
+                    format!("status: {}", parsed_response.status),
+                    MismatchReason::NoSnapshot,
+                )],
+                Some(diff::create_snapshot_diff(
+                    None,
+                    &parsed_response,
+                    compare_options,
+                )),
+            ),
+        };
+        reports.push(RequestReport {
+            index,
+            name: request.name.clone().or(request.heading.clone()),
+            verb: http_file_without_variables.verb.clone(),
+            url: response.request_url.clone(),
+            headers: to_header_map(&response.request_headers),
+            status: parsed_response.status,
+            duration: started.elapsed(),
+            matched_option,
+            captured_variables,
+            mismatches,
+            diff,
+        });
+
+        if matched_option.is_none() {
+            let updated = match closest_option {
+                Some(option_index) => diff::keep_matching_parts(
+                    &snapshots[option_index],
+                    &parsed_response,
+                    compare_options,
+                ),
+                None => parsed_response,
+            };
+            executed_requests[index].snapshot = Some(updated);
+            // Requests after missing captures would fail on the variables they refer to
+            if stop_on_failure || captures_missing {
                 break;
             }
         }
     }
 
-    return Ok((passed, executed_requests));
+    return Ok((reports, executed_requests));
+}
+
+fn to_header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    return headers
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+            (name.to_string(), value)
+        })
+        .collect();
+}
+
+fn to_parse_error(request: &RawInput, errors: Vec<chumsky::error::Simple<char>>) -> ParseError {
+    return ParseError::new(
+        &request.path,
+        request.line_offset,
+        request.column_offset,
+        &request.text,
+        errors,
+    );
 }
 
 fn get_stop_on_failure_option(execute_options: &&ExecuteOptions) -> bool {
@@ -267,25 +395,40 @@ fn detect_patterns(
 fn create_http_content(
     executed_requests: &Vec<ExecutedRequest>,
     update_options: &Option<UpdateOptions>,
-) -> String {
+) -> Result<String, HttpSnapError> {
     let mut imports = Vec::new();
     let mut merged = Vec::new();
-    for executed_request in executed_requests {
+    let mut names = Vec::new();
+    for (index, executed_request) in executed_requests.iter().enumerate() {
         if let Some(import_path) = &executed_request.raw_input.imported_path {
             imports.push(format!("import {}", import_path.display()));
             continue;
         }
         if let Some(snapshot) = &executed_request.snapshot {
             if let Some(options) = update_options {
-                let snapshot_as_str = merger::create_content_with_snapshot(
-                    &executed_request.raw_input.text,
-                    &snapshot,
-                    &options.update_mode,
-                );
+                let raw_input = &executed_request.raw_input;
+                let snapshot_as_str = match get_body_file(raw_input, index, &snapshot, options) {
+                    Some(body_file) => {
+                        write_body_file(raw_input, &body_file, &snapshot)?;
+                        merger::create_content_with_body_file(
+                            raw_input,
+                            &snapshot,
+                            &options.update_mode,
+                            &body_file,
+                        )?
+                    }
+                    None => merger::create_content_with_snapshot(
+                        raw_input,
+                        &snapshot,
+                        &options.update_mode,
+                    )?,
+                };
                 merged.push(snapshot_as_str);
+                names.push(&executed_request.raw_input.name);
             }
         } else {
             merged.push(executed_request.raw_input.text.clone());
+            names.push(&executed_request.raw_input.name);
         }
     }
 
@@ -295,9 +438,74 @@ fn create_http_content(
         result.push_str("\n\n");
     }
 
-    result.push_str(&merged.join("\n\n###\n\n"));
+    for (index, (content, name)) in merged.iter().zip(names).enumerate() {
+        if index > 0 {
+            match name {
+                Some(name) => result.push_str(&format!("\n\n### {name}\n\n")),
+                None => result.push_str("\n\n###\n\n"),
+            }
+        }
+        result.push_str(content);
+    }
 
-    return result;
+    return Ok(result);
+}
+
+// An existing `<<<` reference is rewritten so files don't pile up, long bodies get a new file
+fn get_body_file(
+    raw_input: &RawInput,
+    index: usize,
+    response: &SnapResponse,
+    update_options: &UpdateOptions,
+) -> Option<PathBuf> {
+    if let Some(body_file) = merger::find_body_file(raw_input) {
+        return Some(body_file);
+    }
+
+    let threshold = update_options.body_file_threshold?;
+    let extension = match &response.body {
+        Some(ResponseBody::Json(_)) => "json",
+        Some(ResponseBody::Text(_)) => "txt",
+        _ => return None,
+    };
+    if merger::format_response_body(response).lines().count() <= threshold {
+        return None;
+    }
+
+    let stem = match &raw_input.name {
+        Some(name) => name.replace(|c: char| !c.is_alphanumeric() && c != '-', "_"),
+        None => {
+            let file_stem = raw_input.path.file_stem().unwrap_or_default();
+            format!("{}_{}", file_stem.to_string_lossy(), index)
+        }
+    };
+    let base_dir = raw_input.path.parent().unwrap_or_else(|| Path::new(""));
+    let mut body_file = PathBuf::from("snapshots").join(format!("{stem}.{extension}"));
+    let mut counter = 2;
+    while base_dir.join(&body_file).exists() {
+        body_file = PathBuf::from("snapshots").join(format!("{stem}_{counter}.{extension}"));
+        counter += 1;
+    }
+    return Some(body_file);
+}
+
+fn write_body_file(
+    raw_input: &RawInput,
+    body_file: &Path,
+    response: &SnapResponse,
+) -> Result<(), HttpSnapError> {
+    let base_dir = raw_input.path.parent().unwrap_or_else(|| Path::new(""));
+    let full_path = base_dir.join(body_file);
+    let to_error = |error: std::io::Error| HttpSnapError::SnapshotWrite {
+        path: full_path.clone(),
+        reason: error.to_string(),
+    };
+    if let Some(parent) = full_path.parent() {
+        std::fs::create_dir_all(parent).map_err(to_error)?;
+    }
+    let content = merger::format_response_body(response) + "\n";
+    std::fs::write(&full_path, content).map_err(to_error)?;
+    return Ok(());
 }
 
 fn log_variable_store(variable_store: &variable_store::VariableStore) {
@@ -307,7 +515,7 @@ fn log_variable_store(variable_store: &variable_store::VariableStore) {
         let variables = variable_store
             .variables
             .iter()
-            .map(|(key, value)| format!("{key}: {}", serde_json::to_string_pretty(value).unwrap()))
+            .map(|(key, value)| format!("{key}: {}", to_pretty_json(value)))
             .collect::<Vec<_>>()
             .join("\n");
 
@@ -322,7 +530,25 @@ fn log_request(request: &HttpFile) {
         .map(|header| format!("{}: {}", header.name, header.value))
         .collect::<Vec<_>>()
         .join("\n");
-    let body_pretty = serde_json::to_string_pretty(&request.body).unwrap();
+    let body_pretty = match &request.body {
+        Some(RequestBody::Json(json)) => to_pretty_json(json),
+        Some(RequestBody::Form(fields)) => fields
+            .iter()
+            .map(|field| format!("{}={}", field.name, field.value))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Some(RequestBody::Raw(text)) => text.to_string(),
+        Some(RequestBody::File(path)) => format!("< {}", path.display()),
+        Some(RequestBody::Multipart(parts)) => parts
+            .iter()
+            .map(|part| match &part.content {
+                PartContent::Text(text) => text.to_string(),
+                PartContent::File(path) => format!("< {}", path.display()),
+            })
+            .collect::<Vec<_>>()
+            .join("\n--\n"),
+        None => String::from("null"),
+    };
 
     let log_message = format!(
         "Sending {:?} {}\nHeaders:\n{}\nBody:\n{}",
@@ -331,17 +557,22 @@ fn log_request(request: &HttpFile) {
     log::debug!("{}", log_message);
 }
 
+// Serializing fails on unresolved variables, which are logged as the error instead
+fn to_pretty_json<T: serde::Serialize>(value: &T) -> String {
+    return serde_json::to_string_pretty(value).unwrap_or_else(|error| error.to_string());
+}
+
 fn log_response(response: &HttpResponse) {
     let headers = response
         .headers
         .iter()
-        .map(|(key, value)| format!("{}: {}", key, value.to_str().unwrap()))
+        .map(|(key, value)| format!("{}: {}", key, String::from_utf8_lossy(value.as_bytes())))
         .collect::<Vec<_>>()
         .join("\n");
 
-    let body_pretty = match serde_json::from_str::<Value>(&response.body) {
-        Ok(json) => &serde_json::to_string_pretty(&json).unwrap(),
-        Err(_) => &response.body,
+    let body_pretty = match serde_json::from_slice::<Value>(&response.body) {
+        Ok(json) => to_pretty_json(&json),
+        Err(_) => String::from_utf8_lossy(&response.body).to_string(),
     };
 
     let log_message = format!(
@@ -0,0 +1,36 @@
+use std::cell::RefCell;
+use std::future::Future;
+use std::io::Write;
+
+tokio::task_local! {
+    static LOG_BUFFER: RefCell<Vec<u8>>;
+}
+
+/// Log target that writes into the buffer of the file currently being run,
+/// or directly to stderr when no buffer is active
+pub(crate) struct BufferedStderr;
+
+impl Write for BufferedStderr {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let buffered = LOG_BUFFER.try_with(|buffer| buffer.borrow_mut().extend_from_slice(buf));
+        if buffered.is_err() {
+            return std::io::stderr().write(buf);
+        }
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return std::io::stderr().flush();
+    }
+}
+
+/// Runs the future while collecting its log output, so concurrent files don't interleave
+pub(crate) async fn with_buffered_logs<F: Future>(future: F) -> (F::Output, Vec<u8>) {
+    return LOG_BUFFER
+        .scope(RefCell::new(Vec::new()), async {
+            let output = future.await;
+            let logs = LOG_BUFFER.with(|buffer| buffer.take());
+            (output, logs)
+        })
+        .await;
+}
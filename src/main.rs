@@ -1,27 +1,63 @@
-use crate::cli::{expand_paths, Cli, Commands, GlobalOptions, UpdateMode};
+use crate::cli::{
+    expand_paths, Cli, Commands, GlobalOptions, ReportKind, ReportTarget, UpdateMode,
+};
+use crate::log_buffer::{with_buffered_logs, BufferedStderr};
 use clap::Parser;
+use env_logger::{Target, WriteStyle};
+use futures::{stream, StreamExt};
+use http_snap::diagnostics::ParseError;
+use http_snap::diff::render_unified_diff;
+use http_snap::environment::{load_named_environment, ENVIRONMENT_FILE};
+use http_snap::error::HttpSnapError;
 use http_snap::parser::parse_environment;
-use http_snap::types::{ClientOptions, ExecuteOptions, Mode, Value};
-use http_snap::variable_generator;
+use http_snap::recorder::{record, RecordOptions};
+use http_snap::report::{create_json_report, create_junit_report};
+use http_snap::server::{load_routes, serve};
+use http_snap::types::{ClientOptions, CompareOptions, ExecuteOptions, FileReport, Mode, Value};
+use http_snap::variable_generator::VariableGenerator;
 use http_snap::{run, types};
 use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
 use std::process;
+use tokio::net::TcpListener;
 
 mod cli;
+mod log_buffer;
 
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
 
     let passed = match args.command {
-        Commands::Test { global } => {
-            let client_options = get_client_options(&global);
-            run_test(global, client_options).await
-        }
-        Commands::Update { global, options } => {
-            let client_options = get_client_options(&global);
-            run_update(global, options, client_options).await
+        Commands::Test { global } => match get_client_options(&global) {
+            Ok(client_options) => run_test(global, client_options).await,
+            Err(error) => Err(error),
+        },
+        Commands::Update { global, options } => match get_client_options(&global) {
+            Ok(client_options) => run_update(global, options, client_options).await,
+            Err(error) => Err(error),
+        },
+        Commands::Serve {
+            path,
+            port,
+            match_body,
+            verbose,
+            environment,
+        } => run_serve(path, port, match_body, verbose, environment).await,
+        Commands::Record {
+            target,
+            out,
+            port,
+            host_variable,
+            verbose,
+        } => {
+            let options = RecordOptions {
+                target,
+                out,
+                host_variable,
+            };
+            run_record(options, port, verbose).await
         }
     };
 
@@ -40,14 +76,16 @@ async fn main() {
     }
 }
 
-fn get_client_options(global_options: &GlobalOptions) -> ClientOptions {
+fn get_client_options(
+    global_options: &GlobalOptions,
+) -> Result<ClientOptions, Box<dyn std::error::Error>> {
     return match &global_options.client_options {
-        None => ClientOptions::default(),
+        None => Ok(ClientOptions::default()),
         Some(path) => {
-            let file_content = std::fs::read_to_string(path).unwrap();
+            let file_content = std::fs::read_to_string(path)?;
             let json = file_content.trim_start_matches("\u{feff}");
-            let client_options = serde_json::from_str(&json).expect("Failed to parse JSON");
-            client_options
+            let client_options = serde_json::from_str(&json)?;
+            Ok(client_options)
         }
     };
 }
@@ -56,16 +94,30 @@ async fn run_test(
     global_options: GlobalOptions,
     client_options: ClientOptions,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    setup_logging(global_options.verbose);
-    let expanded_paths = expand_paths(global_options.path);
-    let environment_variables = get_environment_variables(global_options.environment);
-    let execute_options = ExecuteOptions::new_test();
+    setup_logging(global_options.verbose, global_options.jobs.get());
+    load_env_file(global_options.env_file)?;
+    let expanded_paths = expand_paths(global_options.path)?;
+    let seed = global_options.seed.unwrap_or_else(rand::random);
+    let environment_variables = get_environment_variables(
+        global_options.environment,
+        global_options.env_name,
+        Some(seed),
+    )?;
+    let execute_options = ExecuteOptions {
+        compare_options: CompareOptions {
+            numeric_equality: global_options.numeric_equality,
+        },
+        seed: Some(seed),
+        ..ExecuteOptions::new_test()
+    };
 
     return execute(
         expanded_paths,
         environment_variables,
         execute_options,
         client_options,
+        global_options.reports,
+        global_options.jobs.get(),
     )
     .await;
 }
@@ -75,9 +127,15 @@ async fn run_update(
     update_options: cli::UpdateOptions,
     client_options: ClientOptions,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    setup_logging(global_options.verbose);
-    let expanded_paths = expand_paths(global_options.path);
-    let environment_variables = get_environment_variables(global_options.environment);
+    setup_logging(global_options.verbose, global_options.jobs.get());
+    load_env_file(global_options.env_file)?;
+    let expanded_paths = expand_paths(global_options.path)?;
+    let seed = global_options.seed.unwrap_or_else(rand::random);
+    let environment_variables = get_environment_variables(
+        global_options.environment,
+        global_options.env_name,
+        Some(seed),
+    )?;
     let execute_options = ExecuteOptions {
         mode: Mode::Update,
         update_options: Some(types::UpdateOptions {
@@ -88,7 +146,12 @@ async fn run_update(
                 types::UpdateMode::Append
             },
             detectors: get_detectors(update_options.detectors),
+            body_file_threshold: update_options.body_file_threshold,
         }),
+        compare_options: CompareOptions {
+            numeric_equality: global_options.numeric_equality,
+        },
+        seed: Some(seed),
     };
 
     return execute(
@@ -96,10 +159,41 @@ async fn run_update(
         environment_variables,
         execute_options,
         client_options,
+        global_options.reports,
+        global_options.jobs.get(),
     )
     .await;
 }
 
+async fn run_serve(
+    path: PathBuf,
+    port: u16,
+    match_body: bool,
+    verbose: bool,
+    environment: Option<PathBuf>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    setup_logging(verbose, 1);
+    let expanded_paths = expand_paths(path)?;
+    let environment_variables = get_environment_variables(environment, None, None)?;
+    let routes = load_routes(&expanded_paths, &environment_variables)?;
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+
+    serve(listener, routes, match_body).await?;
+    return Ok(true);
+}
+
+async fn run_record(
+    options: RecordOptions,
+    port: u16,
+    verbose: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    setup_logging(verbose, 1);
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+
+    record(listener, options).await?;
+    return Ok(true);
+}
+
 fn get_detectors(input: Vec<cli::Detector>) -> HashSet<types::Detector> {
     if input.contains(&cli::Detector::All) {
         return HashSet::from([types::Detector::Timestamp, types::Detector::Guid]);
@@ -120,58 +214,183 @@ async fn execute(
     environment_variables: HashMap<String, Value>,
     execute_options: ExecuteOptions,
     client_options: ClientOptions,
+    reports: Vec<ReportTarget>,
+    jobs: usize,
 ) -> Result<bool, Box<dyn std::error::Error>> {
     let mut total_count = 0;
     let mut failed_count = 0;
     let mut failed_names = Vec::new();
-    for path in paths {
+    let mut results = Vec::new();
+
+    // Files are started concurrently, but their output and results are handled in order
+    let mut outcomes = stream::iter(paths)
+        .map(|path| {
+            let run_file = run_file(
+                path,
+                &environment_variables,
+                &execute_options,
+                &client_options,
+            );
+            async move {
+                if jobs > 1 {
+                    with_buffered_logs(run_file).await
+                } else {
+                    (run_file.await, Vec::new())
+                }
+            }
+        })
+        .buffered(jobs);
+    while let Some(((path, result), logs)) = outcomes.next().await {
+        std::io::stderr().write_all(&logs)?;
         total_count += 1;
-        log::info!("Running {:?}", path);
-        let result = run(
-            &path,
-            &environment_variables,
-            &execute_options,
-            &client_options,
-        )
-        .await;
-        if result? {
-            log::info!("Test {:?} passed", path);
-        } else {
+        if !matches!(&result, Ok(report) if report.passed()) {
             failed_count += 1;
             failed_names.push(path.display().to_string());
-            log::error!("Test {:?} failed", path);
         }
+        results.push((path, result));
     }
 
+    let seed = execute_options
+        .seed
+        .map(|seed| format!(" with seed {seed}"))
+        .unwrap_or_default();
     log::info!(
-        "Ran {total_count} tests: {0} passed and {failed_count} failed",
+        "Ran {total_count} tests{seed}: {0} passed and {failed_count} failed",
         total_count - failed_count
     );
     if failed_count > 0 {
         let failed = failed_names.join("\n");
         log::error!("The following failed: \n {failed}");
-    } 
+    }
 
+    write_reports(&reports, &results)?;
     return Ok(failed_count == 0);
 }
 
-fn setup_logging(verbose: bool) {
+async fn run_file(
+    path: PathBuf,
+    environment_variables: &HashMap<String, Value>,
+    execute_options: &ExecuteOptions,
+    client_options: &ClientOptions,
+) -> (PathBuf, Result<FileReport, HttpSnapError>) {
+    log::info!("Running {:?}", path);
+    let result = run(
+        &path,
+        environment_variables,
+        execute_options,
+        client_options,
+    )
+    .await;
+    match &result {
+        Ok(report) if report.passed() => log::info!("Test {:?} passed", path),
+        Ok(report) => {
+            log_mismatches(report, matches!(execute_options.mode, Mode::Test));
+            log::error!("Test {:?} failed", path);
+        }
+        Err(error) => log::error!("Test {:?} could not be run:\n{}", path, error),
+    }
+    return (path, result);
+}
+
+fn write_reports(
+    reports: &Vec<ReportTarget>,
+    results: &[(PathBuf, Result<FileReport, HttpSnapError>)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for report in reports {
+        let content = match report.kind {
+            ReportKind::Junit => create_junit_report(results),
+            ReportKind::Json => create_json_report(results)?,
+        };
+        std::fs::write(&report.path, content)?;
+        log::info!("Wrote report to {:?}", report.path);
+    }
+    return Ok(());
+}
+
+fn log_mismatches(report: &FileReport, show_diff: bool) {
+    let color = use_color();
+    for request in &report.requests {
+        if request.matched_option.is_some() {
+            continue;
+        }
+
+        let mismatches = request
+            .mismatches
+            .iter()
+            .map(|mismatch| format!("  {mismatch}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::error!(
+            "Snapshot {0} did NOT match:\n{mismatches}",
+            request.index + 1
+        );
+
+        if let Some(diff) = request.diff.as_ref().filter(|_| show_diff) {
+            log::error!("{}", render_unified_diff(diff, color));
+        }
+    }
+}
+
+fn setup_logging(verbose: bool, jobs: usize) {
     let log_level = if verbose {
         log::LevelFilter::Debug
     } else {
         log::LevelFilter::Info
     };
-    env_logger::Builder::from_default_env()
-        .filter_level(log_level)
-        .init();
+    let mut builder = env_logger::Builder::from_default_env();
+    builder.filter_level(log_level);
+    if jobs > 1 {
+        let write_style = if use_color() {
+            WriteStyle::Always
+        } else {
+            WriteStyle::Never
+        };
+        builder
+            .target(Target::Pipe(Box::new(BufferedStderr)))
+            .write_style(write_style);
+    }
+    builder.init();
+}
+
+fn use_color() -> bool {
+    return std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
 }
 
-fn get_environment_variables(environment: Option<PathBuf>) -> HashMap<String, Value> {
+fn load_env_file(env_file: Option<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    // Variables that are already set in the process are not overwritten
+    return match env_file {
+        Some(path) => match dotenvy::from_path(&path) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(format!("Could not load {}: {error}", path.display()).into()),
+        },
+        // Only the working directory is searched, not its parents
+        None => match dotenvy::from_path(".env") {
+            Err(error) if !error.not_found() => Err(error.into()),
+            _ => Ok(()),
+        },
+    };
+}
+
+fn get_environment_variables(
+    environment: Option<PathBuf>,
+    env_name: Option<String>,
+    seed: Option<u64>,
+) -> Result<HashMap<String, Value>, Box<dyn std::error::Error>> {
+    if let Some(env_name) = env_name {
+        let path = environment.unwrap_or_else(|| PathBuf::from(ENVIRONMENT_FILE));
+        return Ok(load_named_environment(&path, &env_name)?);
+    }
+    let extension = environment.as_ref().and_then(|path| path.extension());
+    if extension.is_some_and(|extension| extension == "json") {
+        return Err("A JSON environment file needs an environment selected with --env".into());
+    }
     let mut env_variables = HashMap::new();
     if let Some(environment) = environment {
-        let env_content = std::fs::read_to_string(environment).unwrap();
-        let parsed_env_variables = parse_environment(&env_content).unwrap();
-        env_variables = variable_generator::generate_variables(parsed_env_variables);
+        let env_content = std::fs::read_to_string(&environment)?;
+        let parsed_env_variables = parse_environment(&env_content)
+            .map_err(|errors| ParseError::new(&environment, 0, 0, &env_content, errors))?;
+        let mut generator = VariableGenerator::for_file(seed, &environment);
+        env_variables = generator.generate_variables(parsed_env_variables)?;
     }
-    return env_variables;
+    return Ok(env_variables);
 }
@@ -1,74 +1,201 @@
-﻿use crate::types::{
-    Array, Comparison, Element, Header, Json, Object, SnapResponse, UpdateMode, Value,
+use crate::error::HttpSnapError;
+use crate::types::{
+    Array, Comparison, Element, Header, Object, RawInput, ResponseBody, SnapResponse, Snapshot,
+    Status, UpdateMode, Value, ValueType,
 };
 use itertools::Itertools;
+use std::path::{Path, PathBuf};
 
 pub fn create_content_with_snapshot(
-    raw_text: &str,
+    raw_input: &RawInput,
     response: &SnapResponse,
     update_mode: &UpdateMode,
-) -> String {
+) -> Result<String, HttpSnapError> {
+    return merge_snapshot(raw_input, &format_snapshot(response), update_mode);
+}
+
+/// Like `create_content_with_snapshot`, but the body is referenced with `<<<` instead of inlined
+pub fn create_content_with_body_file(
+    raw_input: &RawInput,
+    response: &SnapResponse,
+    update_mode: &UpdateMode,
+    body_file: &Path,
+) -> Result<String, HttpSnapError> {
+    let headers = response.headers.values().collect_vec();
+    let snapshot = format_parts(&response.status.to_string(), headers, &None)
+        + &format!("<<< {}", body_file.display());
+    return merge_snapshot(raw_input, &snapshot, update_mode);
+}
+
+/// Finds the `<<<` reference of the existing snapshot, if its body lives in a separate file
+pub fn find_body_file(raw_input: &RawInput) -> Option<PathBuf> {
+    let (_, snapshot) = raw_input.text.split_once("SNAPSHOT")?;
+    return snapshot
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("<<<"))
+        .map(|path| PathBuf::from(path.trim()));
+}
+
+pub fn format_response_body(response: &SnapResponse) -> String {
+    return format_body(&response.body);
+}
+
+fn merge_snapshot(
+    raw_input: &RawInput,
+    snapshot: &str,
+    update_mode: &UpdateMode,
+) -> Result<String, HttpSnapError> {
+    let (raw_text, captures) = split_captures(&raw_input.text);
     let parts_of_file: Vec<&str> = raw_text.split("SNAPSHOT").collect();
-    let snapshot = format_snapshot(response);
     if parts_of_file.len() == 1 {
-        return raw_text.trim().to_owned() + "\n\nSNAPSHOT\n" + &snapshot;
+        return Ok(raw_text.trim().to_owned() + "\n\nSNAPSHOT\n" + snapshot + &captures);
     }
     if parts_of_file.len() == 2 {
         if update_mode == &UpdateMode::Overwrite {
-            return parts_of_file[0].trim().to_owned() + "\n\nSNAPSHOT\n" + &snapshot;
+            let request = parts_of_file[0].trim().to_owned();
+            return Ok(request + "\n\nSNAPSHOT\n" + snapshot + &captures);
         } else {
-            return raw_text.trim().to_owned() + "\n||\n" + &snapshot;
+            return Ok(raw_text.trim().to_owned() + "\n||\n" + snapshot + &captures);
         }
     }
-    panic!("Found more than one snapshot place");
+    return Err(HttpSnapError::SnapshotWrite {
+        path: raw_input.path.clone(),
+        reason: String::from("Found more than one snapshot place"),
+    });
 }
 
-fn format_snapshot(response: &SnapResponse) -> String {
-    let mut formatted = "status: ".to_owned() + &response.status.to_string();
+// The `CAPTURE` section is kept below the updated snapshot
+fn split_captures(raw_text: &str) -> (&str, String) {
+    let start = raw_text
+        .match_indices("\nCAPTURE")
+        .map(|(index, _)| index)
+        .filter(|index| raw_text[index + 1..].lines().next() == Some("CAPTURE"))
+        .last();
+    return match start {
+        Some(start) => (
+            &raw_text[..start],
+            format!("\n\n{}", raw_text[start..].trim()),
+        ),
+        None => (raw_text, String::new()),
+    };
+}
+
+pub fn format_snapshot(response: &SnapResponse) -> String {
+    let headers = response.headers.values().collect_vec();
+    return format_parts(&response.status.to_string(), headers, &response.body);
+}
+
+/// Formats a parsed snapshot the same way as a response, keeping its comparisons
+pub fn format_expected_snapshot(snapshot: &Snapshot) -> String {
+    let status = match &snapshot.status {
+        Status::Value(value) => value.to_string(),
+        Status::Pattern(pattern) => pattern.clone(),
+    };
+    let headers = snapshot.headers.iter().collect_vec();
+    return format_parts(&status, headers, &snapshot.body);
+}
+
+fn format_parts(status: &str, headers: Vec<&Header>, body: &Option<ResponseBody>) -> String {
+    let mut formatted = "status: ".to_owned() + status;
     formatted += "\n\n";
 
-    for name in response.headers.keys().sorted() {
-        let header = response.headers.get(name).unwrap();
+    for header in headers.into_iter().sorted_by(|a, b| a.name.cmp(&b.name)) {
         formatted += &format_header(header);
         formatted += "\n";
     }
 
     formatted += "\n";
-    formatted += &format_body(&response.body);
+    formatted += &format_body(body);
 
     return formatted;
 }
 
 fn format_header(header: &Header) -> String {
-    let formatted = format_comparison(&header.comparison, &Value::from(header.value.to_string()))
+    let value = Value::from(header.value.to_string());
+    let formatted = format_comparison(&header.variable_store, &header.comparison, &value)
         .unwrap_or_else(|| header.value.to_string());
     return format!("{}: {}", header.name, formatted);
 }
 
-fn format_body(body: &Option<Json>) -> String {
-    return match body { 
-        Some(json) => format_element(&json.element, 0),
-        None => String::new()
+fn format_body(body: &Option<ResponseBody>) -> String {
+    return match body {
+        Some(ResponseBody::Json(json)) => format_element(&json.element, 0),
+        Some(ResponseBody::Text(text)) => format_text(text.trim_end()),
+        Some(ResponseBody::Binary { size, sha256 }) => {
+            format!("binary: {size} bytes, sha256: {sha256}")
+        }
+        None => String::new(),
     };
 }
 
+// Text is written verbatim, unless it would be read back as another kind of body or has
+// lines that end the snapshot. Then it is indented below `text: |`
+fn format_text(text: &str) -> String {
+    let other_body = ["{", "[", "binary: ", "text: |", "<<<"]
+        .iter()
+        .any(|start| text.trim_start().starts_with(start));
+    let ends_snapshot = text
+        .lines()
+        .any(|line| line.starts_with("||") || line.starts_with("CAPTURE"));
+    if !other_body && !ends_snapshot {
+        return text.to_string();
+    }
+    let indented = text
+        .lines()
+        .map(|line| match line {
+            "" => String::new(),
+            line => format!("  {line}"),
+        })
+        .join("\n");
+    return format!("text: |\n{indented}");
+}
+
 fn format_element(element: &Element, indent: usize) -> String {
-    return format_comparison(&element.comparison, &element.value)
+    return format_comparison(&element.variable_store, &element.comparison, &element.value)
         .unwrap_or_else(|| format_value(&element.value, indent));
 }
 
-fn format_comparison(comparison: &Option<Comparison>, value: &Value) -> Option<String> {
+// The name of a stored variable is kept, so later requests can still refer to it
+fn format_comparison(
+    variable_store: &Option<String>,
+    comparison: &Option<Comparison>,
+    value: &Value,
+) -> Option<String> {
+    let name = variable_store.as_deref().unwrap_or("_");
     return match comparison {
-        Some(Comparison::Ignore) => Some(format!("{{{{_:_}}}}")),
+        Some(Comparison::Ignore) => Some(format!("{{{{{name}:_}}}}")),
+        Some(Comparison::Exact) | None if variable_store.is_some() => {
+            Some(format!("{{{{{name}:{}}}}}", format_value(value, 0)))
+        }
         Some(Comparison::TimestampFormat(pattern)) => Some(format!(
-            "{{{{_:timestamp(\"{pattern}\"):{}}}}}",
+            "{{{{{name}:timestamp(\"{pattern}\"):{}}}}}",
+            format_value(value, 0)
+        )),
+        Some(Comparison::Guid) => Some(format!("{{{{{name}:guid:{}}}}}", format_value(value, 0))),
+        Some(Comparison::Numeric(comparison)) => Some(format!(
+            "{{{{{name}:{comparison}:{}}}}}",
+            format_value(value, 0)
+        )),
+        Some(Comparison::Type(value_type)) => Some(format_type_comparison(name, value_type, value)),
+        Some(Comparison::Regex(pattern)) => Some(format!(
+            "{{{{{name}:regex({}):{}}}}}",
+            serde_json::to_string(pattern).unwrap(),
             format_value(value, 0)
         )),
-        Some(Comparison::Guid) => Some(format!("{{{{_:guid:{}}}}}", format_value(value, 0))),
         _ => None,
     };
 }
 
+// Only scalar example values are kept, arrays and objects would not fit on one line
+fn format_type_comparison(name: &str, value_type: &ValueType, value: &Value) -> String {
+    return match value {
+        Value::Object(_) | Value::Array(_) | Value::Null() => {
+            format!("{{{{{name}:{value_type}}}}}")
+        }
+        _ => format!("{{{{{name}:{value_type}:{}}}}}", format_value(value, 0)),
+    };
+}
+
 fn format_value(value: &Value, indent: usize) -> String {
     match value {
         Value::VariableReference(name) => format!("{{{{{name}}}}}"), // Gives {{name}}
@@ -83,11 +210,11 @@ fn format_value(value: &Value, indent: usize) -> String {
 
 fn format_object(object: &Object, indent: usize) -> String {
     if object.members.is_empty() {
-        return String::from("{}");
+        return String::from(if object.partial { "{ ... }" } else { "{}" });
     }
 
     let indent_str = "  ".repeat(indent + 1);
-    let members = object
+    let mut members = object
         .members
         .iter()
         .map(|member| {
@@ -95,23 +222,51 @@ fn format_object(object: &Object, indent: usize) -> String {
             format!("{}\"{}\": {}", indent_str, member.key, value)
         })
         .join(",\n");
+    if object.partial {
+        members += &format!(",\n{indent_str}...");
+    }
 
     let closing_indent = "  ".repeat(indent);
     return format!("{{\n{members}\n{closing_indent}}}");
 }
 
 fn format_array(array: &Array, indent: usize) -> String {
-    let elements = array.get_known_elements();
-    if elements.len() == 0 {
-        return String::from("[]");
-    }
-
+    let elements = match array {
+        Array::VariableReference(name) => return format!("{{{{{name}}}}}"),
+        Array::Literal(elements)
+        | Array::StartsWith(elements)
+        | Array::Contains(elements)
+        | Array::EndsWith(elements)
+        | Array::Unordered(elements)
+        | Array::ContainsAll(elements) => elements,
+    };
     let indent_str = "  ".repeat(indent + 1);
     let formatted_elements = elements
         .iter()
         .map(|element| format!("{}{}", indent_str, format_element(element, indent + 1)))
         .join(",\n");
 
+    let dots = format!("{indent_str}...");
+    let lines = match array {
+        Array::StartsWith(_) => vec![formatted_elements, dots],
+        Array::Contains(_) => vec![dots.clone(), formatted_elements, dots],
+        Array::EndsWith(_) => vec![dots, formatted_elements],
+        Array::ContainsAll(_) => vec![formatted_elements, dots],
+        _ => vec![formatted_elements],
+    };
+    let content = lines.into_iter().filter(|line| !line.is_empty()).join("\n");
+    if content.is_empty() {
+        return match array {
+            Array::Unordered(_) => String::from("[~ ~]"),
+            _ => String::from("[]"),
+        };
+    }
+
     let closing_indent = "  ".repeat(indent);
-    return format!("[\n{}\n{}]", formatted_elements, closing_indent);
+    return match array {
+        Array::Unordered(_) | Array::ContainsAll(_) => {
+            format!("[~\n{}\n{}~]", content, closing_indent)
+        }
+        _ => format!("[\n{}\n{}]", content, closing_indent),
+    };
 }
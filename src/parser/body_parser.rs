@@ -1,20 +1,179 @@
-﻿use crate::parser::variable_parser::{
+﻿use crate::parser::header_parser::headers_parser;
+use crate::parser::variable_parser::{
     variable_name_parser, variable_name_string_parser, variable_store_body_parser,
 };
 use crate::types::*;
 use chumsky::error::Simple;
 use chumsky::prelude::*;
-use chumsky::text::{digits, int, whitespace};
+use chumsky::text::{digits, int, newline, whitespace};
 use chumsky::Parser;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 // Based on https://www.json.org/json-en.html
 pub(crate) fn body_parser(
     comparison: bool,
 ) -> impl Parser<char, Option<Json>, Error = Simple<char>> {
-    let no_body = empty().map(|_| None);
+    // or_not keeps the error from a malformed body, so it can be reported instead of the
+    // error from whatever comes after the body
+    return json_parser(comparison).or_not();
+}
+
+/// Parses the request body in the format given by the `Content-Type` header,
+/// which is JSON when the header is missing
+pub(crate) fn request_body_parser(
+    content_type: Option<String>,
+) -> BoxedParser<'static, char, Option<RequestBody>, Simple<char>> {
+    let original = content_type.unwrap_or_default();
+    let content_type = original.to_lowercase();
+    let file = whitespace()
+        .ignore_then(file_reference_parser("<"))
+        .map(RequestBody::File);
+    if is_json(&content_type) {
+        return file
+            .or(json_parser(false).map(RequestBody::Json))
+            .or_not()
+            .boxed();
+    }
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        return file
+            .or(whitespace().ignore_then(form_parser()))
+            .or_not()
+            .boxed();
+    }
+    if content_type.starts_with("multipart/form-data") {
+        // The boundary is case sensitive
+        if let Some(boundary) = boundary(&original) {
+            return file
+                .or(whitespace().ignore_then(multipart_parser(boundary)))
+                .or_not()
+                .boxed();
+        }
+    }
+    return file
+        .or(whitespace().ignore_then(raw_text_parser()))
+        .or_not()
+        .boxed();
+}
+
+/// Bodies are JSON when the content type says so or when there is no content type
+pub(crate) fn is_json(content_type: &str) -> bool {
+    return content_type.is_empty() || content_type.to_lowercase().contains("json");
+}
+
+fn boundary(content_type: &str) -> Option<String> {
+    return content_type
+        .split(';')
+        .filter_map(|parameter| parameter.trim().split_once('='))
+        .filter(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary.trim_matches('"').to_string())
+        .next();
+}
+
+// Each part starts with `--boundary` and its headers, the body ends with `--boundary--`
+fn multipart_parser(boundary: String) -> impl Parser<char, RequestBody, Error = Simple<char>> {
+    let delimiter = format!("--{boundary}");
+    let file = file_reference_parser("<")
+        .map(PartContent::File)
+        .then_ignore(whitespace());
+    let text = part_text_parser(delimiter.clone()).map(PartContent::Text);
+
+    let part = just(delimiter.clone())
+        .ignore_then(newline())
+        .ignore_then(headers_parser(false))
+        .then_ignore(whitespace())
+        .then(file.or(text))
+        .map(|(headers, content)| MultipartPart { headers, content });
+
+    return part
+        .repeated()
+        .at_least(1)
+        .then_ignore(just(format!("{delimiter}--")))
+        .map(RequestBody::Multipart);
+}
+
+// A file whose content is sent, given as `< ./path/to/file`.
+// The space keeps it apart from raw XML bodies like `<order>`.
+pub(crate) fn file_reference_parser(
+    marker: &'static str,
+) -> impl Parser<char, PathBuf, Error = Simple<char>> {
+    let path = filter(|c: &char| *c != '\n' && *c != '\r')
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+    return just(marker)
+        .ignore_then(one_of(" \t").repeated().at_least(1))
+        .ignore_then(path)
+        .map(|path| PathBuf::from(path.trim_end()));
+}
+
+// Text of a part runs until the line with the next delimiter
+fn part_text_parser(delimiter: String) -> impl Parser<char, CompositeString, Error = Simple<char>> {
+    let literal = newline()
+        .then(just(delimiter))
+        .not()
+        .map(|c: char| CompositeStringPart::Literal(c.to_string()));
+
+    return variable_name_parser()
+        .or(literal)
+        .repeated()
+        .then_ignore(newline())
+        .map(|parts| {
+            let mut merged_parts = CompositeStringPart::merge_literals(parts);
+            if let Some(CompositeStringPart::Literal(last)) = merged_parts.last_mut() {
+                last.truncate(last.trim_end().len());
+            }
+            CompositeString::new(merged_parts)
+        });
+}
+
+// Fields are separated by `&` or by new lines, like `name={{name}}&password=secret`
+fn form_parser() -> impl Parser<char, RequestBody, Error = Simple<char>> {
+    let field = delimited_text_parser("=&")
+        .then_ignore(just('='))
+        .then(delimited_text_parser("&").or_not())
+        .map(|(name, value)| FormField {
+            name,
+            value: value.unwrap_or(CompositeString::new(Vec::new())),
+        });
+
+    return field
+        .separated_by(just('&').ignored().or(newline()))
+        .at_least(1)
+        .map(RequestBody::Form);
+}
+
+// Takes everything up to the snapshot, leaving out the trailing whitespace
+fn raw_text_parser() -> impl Parser<char, RequestBody, Error = Simple<char>> {
+    let literal = newline()
+        .then(just("SNAPSHOT"))
+        .not()
+        .map(|c: char| CompositeStringPart::Literal(c.to_string()));
+    // Without a body the snapshot follows right after the whitespace
+    let not_snapshot = just("SNAPSHOT").not().rewind();
+
+    return not_snapshot
+        .ignore_then(variable_name_parser().or(literal).repeated().at_least(1))
+        .map(|parts| {
+            let mut merged_parts = CompositeStringPart::merge_literals(parts);
+            if let Some(CompositeStringPart::Literal(last)) = merged_parts.last_mut() {
+                last.truncate(last.trim_end().len());
+            }
+            RequestBody::Raw(CompositeString::new(merged_parts))
+        });
+}
 
-    return json_parser(comparison).map(|body| Some(body)).or(no_body);
+/// Parses text on a single line up to one of the terminators, with `{{variable}}` support
+pub(crate) fn delimited_text_parser(
+    terminators: &'static str,
+) -> impl Parser<char, CompositeString, Error = Simple<char>> {
+    let literal = filter(move |c: &char| !terminators.contains(*c) && *c != '\n' && *c != '\r')
+        .map(|c: char| CompositeStringPart::Literal(c.to_string()));
+    return variable_name_parser()
+        .or(literal)
+        .repeated()
+        .at_least(1)
+        .map(|parts| CompositeString::new(CompositeStringPart::merge_literals(parts)));
 }
 
 fn json_parser(comparison: bool) -> impl Parser<char, Json, Error = Simple<char>> {
@@ -23,13 +182,14 @@ fn json_parser(comparison: bool) -> impl Parser<char, Json, Error = Simple<char>
 
 pub(crate) fn value_parser(
     element_parser: impl Parser<char, Element, Error = Simple<char>> + Clone,
+    comparison: bool,
 ) -> impl Parser<char, Value, Error = Simple<char>> {
     let variable = variable_name_string_parser().map(|name| Value::VariableReference(name));
     let boolean = (just("true").map(|_| Value::Boolean(true)))
         .or(just("false").map(|_| Value::Boolean(false)));
     let null = just("null").map(|_| Value::Null());
 
-    return object_parser(element_parser.clone())
+    return object_parser(element_parser.clone(), comparison)
         .or(array_parser(element_parser.clone()))
         .or(string_value_parser())
         .or(number_value_parser())
@@ -40,16 +200,34 @@ pub(crate) fn value_parser(
 
 fn object_parser(
     element_parser: impl Parser<char, Element, Error = Simple<char>> + Clone,
+    comparison: bool,
 ) -> impl Parser<char, Value, Error = Simple<char>> {
     let empty = whitespace().delimited_by(just("{"), just("}")).map(|_| {
         Value::Object(Object {
             members: Vec::new(),
+            partial: false,
         })
     });
 
+    let dots = whitespace()
+        .then(just(",").or_not())
+        .then(whitespace())
+        .then(just("..."))
+        .then(whitespace())
+        // Only snapshots can leave out members, request bodies are sent as written
+        .try_map(move |dots, span| match comparison {
+            true => Ok(dots),
+            false => Err(Simple::custom(span, "`...` is only allowed in snapshots")),
+        });
     let members = members_parser(element_parser)
+        .then(dots.or_not())
         .delimited_by(just("{"), just("}"))
-        .map(|members| Value::Object(Object { members }));
+        .map(|(members, dots)| {
+            Value::Object(Object {
+                members,
+                partial: dots.is_some(),
+            })
+        });
 
     return empty.or(members);
 }
@@ -88,6 +266,10 @@ fn array_parser(
         .delimited_by(just("["), just("]"))
         .map(|_| Value::Array(Array::Literal(Vec::new())));
 
+    let empty_unordered = whitespace()
+        .delimited_by(just("[~"), just("~]"))
+        .map(|_| Value::Array(Array::Unordered(Vec::new())));
+
     let literal = elements_parser(element_parser.clone())
         .delimited_by(just("["), just("]"))
         .map(|elements| Value::Array(Array::Literal(elements)));
@@ -109,7 +291,24 @@ fn array_parser(
         .delimited_by(just("["), just("]"))
         .map(|elements| Value::Array(Array::EndsWith(elements)));
 
-    return choice((empty, literal, starts_with, contains, ends_with));
+    let unordered = elements_parser(element_parser)
+        .then(dots.or_not())
+        .then_ignore(whitespace())
+        .delimited_by(just("[~"), just("~]"))
+        .map(|(elements, dots)| match dots {
+            Some(_) => Value::Array(Array::ContainsAll(elements)),
+            None => Value::Array(Array::Unordered(elements)),
+        });
+
+    return choice((
+        empty_unordered,
+        unordered,
+        empty,
+        literal,
+        starts_with,
+        contains,
+        ends_with,
+    ));
 }
 
 fn elements_parser(
@@ -139,7 +338,7 @@ fn element_compare_parser() -> Rc<dyn Parser<char, Element, Error = Simple<char>
                         comparison,
                     },
                 ),
-                value_parser(element_compare_parser).map(|value| Element {
+                value_parser(element_compare_parser, true).map(|value| Element {
                     value,
                     variable_store: None,
                     comparison: Some(Comparison::Exact),
@@ -152,7 +351,7 @@ fn element_compare_parser() -> Rc<dyn Parser<char, Element, Error = Simple<char>
 fn element_no_compare_parser() -> Rc<dyn Parser<char, Element, Error = Simple<char>>> {
     return Rc::new(recursive(|element_no_compare_parser| {
         whitespace()
-            .ignore_then(value_parser(element_no_compare_parser))
+            .ignore_then(value_parser(element_no_compare_parser, false))
             .then_ignore(whitespace())
             .then_ignore(whitespace())
             .map(|value| Element {
@@ -219,7 +418,7 @@ fn number_value_parser() -> impl Parser<char, Value, Error = Simple<char>> {
     return number_parser().map(|value| Value::Number(value));
 }
 
-fn number_parser() -> impl Parser<char, Number, Error = Simple<char>> {
+pub(crate) fn number_parser() -> impl Parser<char, Number, Error = Simple<char>> {
     return exponent_parser().or(fraction_parser()).or(integer_parser());
 }
 
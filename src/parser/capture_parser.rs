@@ -0,0 +1,75 @@
+use crate::parser::body_parser::characters_parser;
+use crate::types::{Capture, CaptureSource, PathSegment};
+use chumsky::error::Simple;
+use chumsky::prelude::*;
+use chumsky::text::{whitespace, Character};
+use chumsky::Parser;
+
+/// Parses the optional `CAPTURE` section at the end of a request
+pub(crate) fn captures_parser() -> impl Parser<char, Vec<Capture>, Error = Simple<char>> {
+    let inline_whitespace = || filter(|c: &char| c.is_inline_whitespace()).repeated();
+    let capture = just('@')
+        .ignore_then(text::ident())
+        .then_ignore(inline_whitespace())
+        .then_ignore(just('='))
+        .then_ignore(inline_whitespace())
+        .then(capture_source_parser())
+        .map(|(name, source)| Capture { name, source });
+
+    let captures = just("CAPTURE").ignore_then(capture.padded().repeated());
+    return whitespace()
+        .ignore_then(captures.or_not())
+        .then_ignore(whitespace().then(end()))
+        .map(|captures| captures.unwrap_or_default());
+}
+
+fn capture_source_parser() -> impl Parser<char, CaptureSource, Error = Simple<char>> {
+    let name = filter(|c: &char| *c != ')' && *c != '\n')
+        .repeated()
+        .at_least(1);
+    let header = name
+        .delimited_by(just("header("), just(')'))
+        .collect::<String>()
+        .map(|name| CaptureSource::Header(name.trim().to_lowercase()));
+
+    return choice((
+        header,
+        json_path_parser().map(CaptureSource::Body),
+        json_pointer_parser().map(CaptureSource::Body),
+    ));
+}
+
+// A subset of JSONPath: `$`, followed by `.key`, `["key"]` or `[0]`
+fn json_path_parser() -> impl Parser<char, Vec<PathSegment>, Error = Simple<char>> {
+    let key = filter(|c: &char| !c.is_whitespace() && *c != '.' && *c != '[')
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+    let index = text::int(10).try_map(|index: String, span| {
+        return index
+            .parse()
+            .map(PathSegment::Index)
+            .map_err(|error| Simple::custom(span, format!("Invalid index: {error}")));
+    });
+    let quoted_key = characters_parser()
+        .delimited_by(just('"'), just('"'))
+        .map(|key| PathSegment::Key(key.to_string()));
+
+    return just('$').ignore_then(
+        choice((
+            just('.').ignore_then(key).map(PathSegment::Key),
+            index.or(quoted_key).delimited_by(just('['), just(']')),
+        ))
+        .repeated(),
+    );
+}
+
+// A JSON pointer as described in RFC 6901, like `/items/0/id`
+fn json_pointer_parser() -> impl Parser<char, Vec<PathSegment>, Error = Simple<char>> {
+    let segment = filter(|c: &char| !c.is_whitespace() && *c != '/')
+        .repeated()
+        .collect::<String>()
+        .map(|segment| PathSegment::Key(segment.replace("~1", "/").replace("~0", "~")));
+
+    return just('/').ignore_then(segment).repeated().at_least(1);
+}
@@ -17,25 +17,22 @@ pub(crate) fn headers_parser(
 }
 
 fn headers_no_compare_parser() -> Box<dyn Parser<char, Vec<Header>, Error = Simple<char>>> {
-    return Box::new(
+    return Box::new(header_lines_parser(
         header_key()
             .then_ignore(just(':'))
             .then_ignore(repeated_spaces())
             .then(header_value())
-            .then_ignore(whitespace())
             .map(|(name, value)| Header {
                 name,
                 value,
                 variable_store: None,
                 comparison: None,
-            })
-            .padded()
-            .repeated(),
-    );
+            }),
+    ));
 }
 
 fn headers_compare_parser() -> Box<dyn Parser<char, Vec<Header>, Error = Simple<char>>> {
-    return Box::new(
+    return Box::new(header_lines_parser(
         header_key()
             .then_ignore(just(':'))
             .then_ignore(repeated_spaces())
@@ -43,16 +40,21 @@ fn headers_compare_parser() -> Box<dyn Parser<char, Vec<Header>, Error = Simple<
                 variable_store_header_parser()
                     .or(header_value().map(|value| (None, (value, Some(Comparison::Exact))))),
             )
-            .then_ignore(whitespace())
             .map(|(name, (variable_store, (value, comparison)))| Header {
                 name,
                 value,
                 variable_store,
                 comparison,
-            })
-            .padded()
-            .repeated(),
-    );
+            }),
+    ));
+}
+
+// Headers are on consecutive lines, so a blank line ends them even when the body
+// looks like a header, for example a text body starting with `Error: `
+fn header_lines_parser(
+    header: impl Parser<char, Header, Error = Simple<char>>,
+) -> impl Parser<char, Vec<Header>, Error = Simple<char>> {
+    return whitespace().ignore_then(header.separated_by(text::newline()));
 }
 
 fn repeated_spaces() -> impl Parser<char, Vec<char>, Error = Simple<char>> {
@@ -1,38 +1,74 @@
 ﻿mod body_parser;
+mod capture_parser;
 mod header_parser;
 mod snapshot_parser;
 mod url_parser;
 mod variable_parser;
 
 use crate::client::HttpResponse;
+use crate::diagnostics::ParseError;
+use crate::error::HttpSnapError;
 use crate::types::*;
 use chumsky::error::Simple;
-use chumsky::prelude::just;
+use chumsky::prelude::{any, end, just};
 use chumsky::text::int;
 use chumsky::Parser;
+use reqwest::header::CONTENT_TYPE;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-fn parser() -> impl Parser<char, HttpFile, Error = Simple<char>> {
-    let base = variable_parser::variables_parser(false)
+struct RequestHead {
+    variables: HashMap<String, Variable>,
+    verb: HttpVerb,
+    url: CompositeString,
+    query_parameters: Vec<QueryParameter>,
+    headers: Vec<Header>,
+}
+
+fn head_parser() -> impl Parser<char, RequestHead, Error = Simple<char>> {
+    return variable_parser::variables_parser(false)
         .then(url_parser::verb_parser())
         .then(url_parser::url_parser())
+        .then(url_parser::query_parameters_parser())
         .then(header_parser::headers_parser(false))
-        .then(body_parser::body_parser(false))
-        .then(snapshot_parser::snapshots_parser())
         .map(
-            |(((((variables, verb), url), headers), body), snapshots)| HttpFile {
+            |((((variables, verb), url), query_parameters), headers)| RequestHead {
                 variables,
                 verb,
                 url,
+                query_parameters,
                 headers,
-                body,
-                snapshots,
             },
         );
+}
+
+fn parser(content_type: Option<String>) -> impl Parser<char, HttpFile, Error = Simple<char>> {
+    let base = head_parser()
+        .then(body_parser::request_body_parser(content_type))
+        .then(snapshot_parser::snapshots_parser())
+        .then(capture_parser::captures_parser())
+        .map(|(((head, body), snapshots), captures)| HttpFile {
+            variables: head.variables,
+            verb: head.verb,
+            url: head.url,
+            query_parameters: head.query_parameters,
+            headers: head.headers,
+            body,
+            snapshots,
+            captures,
+        });
 
     return base;
 }
 
+fn content_type(headers: &Vec<Header>) -> Option<String> {
+    return headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("content-type"))
+        .map(|header| header.value.to_string());
+}
+
 pub fn try_parse_delay(input: &str) -> Result<Option<u64>, Vec<Simple<char>>> {
     let parser = (just("delay")
         .ignore_then(just(' ').repeated())
@@ -43,16 +79,69 @@ pub fn try_parse_delay(input: &str) -> Result<Option<u64>, Vec<Simple<char>>> {
 }
 
 pub fn parse_file(input: &str) -> Result<HttpFile, Vec<Simple<char>>> {
-    let result = parser().parse(input);
+    // The headers are read first, since they decide how the body is parsed
+    let content_type = head_parser()
+        .then_ignore(any().repeated())
+        .parse(input)
+        .ok()
+        .and_then(|head| content_type(&head.headers));
+    let result = parser(content_type).parse(input);
     return result;
 }
 
-pub async fn parse_response(
-    response: &HttpResponse,
-) -> Result<SnapResponse, Box<dyn std::error::Error>> {
-    let body = body_parser::body_parser(false)
-        .parse(response.body.clone())
-        .unwrap();
+/// Resolves files referenced by the body relative to the file containing the request.
+/// A body file with a JSON content type is parsed, so its variables are replaced like inline ones.
+pub fn load_file_references(
+    mut http_file: HttpFile,
+    path: &PathBuf,
+) -> Result<HttpFile, HttpSnapError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    match &mut http_file.body {
+        Some(RequestBody::Multipart(parts)) => {
+            for part in parts {
+                if let PartContent::File(file) = &mut part.content {
+                    *file = base_dir.join(&file);
+                }
+            }
+        }
+        Some(RequestBody::File(file)) => {
+            let full_path = base_dir.join(&file);
+            let content_type = content_type(&http_file.headers).unwrap_or_default();
+            http_file.body = if body_parser::is_json(&content_type) {
+                let text = std::fs::read_to_string(&full_path)
+                    .map_err(|error| HttpSnapError::io(&full_path, error))?;
+                body_parser::body_parser(false)
+                    .then_ignore(end())
+                    .parse(text.as_str())
+                    .map_err(|errors| ParseError::new(&full_path, 0, 0, &text, errors))?
+                    .map(RequestBody::Json)
+            } else {
+                Some(RequestBody::File(full_path))
+            };
+        }
+        _ => (),
+    }
+    for snapshot in &mut http_file.snapshots {
+        if let Some(file) = &snapshot.body_file {
+            let full_path = base_dir.join(file);
+            let text = std::fs::read_to_string(&full_path)
+                .map_err(|error| HttpSnapError::io(&full_path, error))?;
+            snapshot.body = snapshot_parser::snapshot_body_parser()
+                .then_ignore(end())
+                .parse(text.as_str())
+                .map_err(|errors| ParseError::new(&full_path, 0, 0, &text, errors))?;
+        }
+    }
+    return Ok(http_file);
+}
+
+pub async fn parse_response(response: &HttpResponse) -> Result<SnapResponse, HttpSnapError> {
+    let content_type = response
+        .headers
+        .get(CONTENT_TYPE)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).to_lowercase())
+        .unwrap_or_default();
+    let body = parse_response_body(&content_type, &response.body);
     let headers = response
         .headers
         .iter()
@@ -61,7 +150,7 @@ pub async fn parse_response(
             let header = Header {
                 name: name.clone(),
                 value: CompositeString::new(vec![CompositeStringPart::Literal(
-                    value.to_str().unwrap().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).to_string(),
                 )]),
                 variable_store: None,
                 comparison: None,
@@ -76,6 +165,50 @@ pub async fn parse_response(
     });
 }
 
+// JSON that doesn't parse falls back to text, and text that isn't UTF-8 to binary
+fn parse_response_body(content_type: &str, body: &[u8]) -> Option<ResponseBody> {
+    if body.iter().all(|byte| byte.is_ascii_whitespace()) {
+        return None;
+    }
+
+    let text = std::str::from_utf8(body).ok();
+    if body_parser::is_json(content_type) {
+        if let Some(json) = text.and_then(parse_json) {
+            return Some(ResponseBody::Json(json));
+        }
+    }
+    if let Some(text) = text {
+        if content_type.is_empty() || is_text(content_type) {
+            return Some(ResponseBody::Text(text.to_string()));
+        }
+    }
+    return Some(ResponseBody::Binary {
+        size: body.len(),
+        sha256: format!("{:x}", Sha256::digest(body)),
+    });
+}
+
+fn is_text(content_type: &str) -> bool {
+    let textual = [
+        "json",
+        "xml",
+        "html",
+        "javascript",
+        "csv",
+        "yaml",
+        "form-urlencoded",
+    ];
+    return content_type.starts_with("text/") || textual.iter().any(|t| content_type.contains(t));
+}
+
+pub(crate) fn parse_json(text: &str) -> Option<Json> {
+    return body_parser::body_parser(false)
+        .then_ignore(end())
+        .parse(text)
+        .ok()
+        .flatten();
+}
+
 pub fn parse_environment(input: &str) -> Result<HashMap<String, Variable>, Vec<Simple<char>>> {
     return variable_parser::variables_parser(false)
         .map(|vars| vars)
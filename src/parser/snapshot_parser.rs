@@ -1,36 +1,122 @@
-﻿use crate::parser::body_parser::{body_parser, characters_parser};
+﻿use crate::parser::body_parser::{
+    body_parser, characters_parser, file_reference_parser, number_parser,
+};
 use crate::parser::header_parser::headers_parser;
-use crate::types::{Comparison, Number, Snapshot, Status};
+use crate::types::{
+    Comparison, Number, NumericComparison, ResponseBody, Snapshot, Status, ValueType,
+};
 use chumsky::error::Simple;
 use chumsky::prelude::*;
 use chumsky::text::whitespace;
 use chumsky::Parser;
+use regex::Regex;
 
 pub(crate) fn snapshots_parser() -> impl Parser<char, Vec<Snapshot>, Error = Simple<char>> {
-    let no_snapshot = end().map(|_| Vec::new());
+    let no_snapshot = section_end().map(|_| Vec::new());
 
     let snapshot = status_parser()
         .then_ignore(whitespace())
         .then(headers_parser(true))
         .then_ignore(whitespace())
-        .then(body_parser(true))
-        .map(|((status, headers), body)| Snapshot {
+        .then(
+            file_reference_parser("<<<")
+                .map(|file| (None, Some(file)))
+                .or(snapshot_body_parser().map(|body| (body, None))),
+        )
+        .map(|((status, headers), (body, body_file))| Snapshot {
             status,
             headers,
             body,
+            body_file,
         });
 
     let snapshots = just("SNAPSHOT").ignore_then(whitespace()).ignore_then(
         snapshot
             .padded()
             .separated_by(just("||"))
-            .then_ignore(whitespace().then(end()))
+            .then_ignore(section_end())
             .map(|s| s),
     );
 
     return whitespace().ignore_then(no_snapshot.or(snapshots));
 }
 
+// Snapshots run until the end of the request or its `CAPTURE` section
+fn section_end() -> impl Parser<char, (), Error = Simple<char>> {
+    return whitespace()
+        .then(just("CAPTURE").ignored().or(end()))
+        .ignored()
+        .rewind();
+}
+
+// Bodies that are not JSON are text, unless they describe a binary body
+pub(crate) fn snapshot_body_parser() -> impl Parser<char, Option<ResponseBody>, Error = Simple<char>>
+{
+    let option_end = whitespace()
+        .then(just("||").ignored().or(section_end()))
+        .rewind();
+    let json = body_parser(true)
+        .then_ignore(option_end)
+        .map(|json| json.map(ResponseBody::Json));
+
+    // Bodies that look like JSON or binary are never text, so their syntax errors are reported
+    let not_json = whitespace()
+        .then(just("binary: ").not().rewind())
+        .then(none_of("{["))
+        .rewind();
+    let text = not_json
+        .ignore_then(text_content_parser())
+        .map(|text| Some(ResponseBody::Text(text.trim_end().to_string())));
+
+    return binary_body_parser()
+        .or(indented_text_parser())
+        .map(Some)
+        .or(json)
+        .or(text);
+}
+
+fn text_content_parser() -> impl Parser<char, String, Error = Simple<char>> {
+    return text::newline()
+        .then(just("||").or(just("CAPTURE")))
+        .not()
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+}
+
+// Written by the merger for text that would otherwise be read as another kind of body
+fn indented_text_parser() -> impl Parser<char, ResponseBody, Error = Simple<char>> {
+    return just("text: |")
+        .ignore_then(text_content_parser())
+        .map(|content| {
+            let text = content
+                .lines()
+                .skip(1)
+                .map(|line| line.strip_prefix("  ").unwrap_or(line))
+                .collect::<Vec<_>>()
+                .join("\n");
+            return ResponseBody::Text(text.trim_end().to_string());
+        });
+}
+
+// Written as `binary: 1024 bytes, sha256: 9f86d081...`
+fn binary_body_parser() -> impl Parser<char, ResponseBody, Error = Simple<char>> {
+    let sha256 = one_of("0123456789abcdef")
+        .repeated()
+        .exactly(64)
+        .collect::<String>();
+    return just("binary: ")
+        .ignore_then(text::int(10))
+        .then_ignore(just(" bytes, sha256: "))
+        .then(sha256)
+        .try_map(|(size, sha256): (String, String), span| {
+            let size = size
+                .parse()
+                .map_err(|error| Simple::custom(span, format!("Invalid size: {error}")))?;
+            return Ok(ResponseBody::Binary { size, sha256 });
+        });
+}
+
 pub(crate) fn ignore_comparison_parser() -> impl Parser<char, Comparison, Error = Simple<char>> {
     return whitespace()
         .then(just("_"))
@@ -45,6 +131,72 @@ pub(crate) fn timestamp_format_parser() -> impl Parser<char, Comparison, Error =
         .map(|pattern| Comparison::TimestampFormat(pattern));
 }
 
+pub(crate) fn regex_format_parser() -> impl Parser<char, Comparison, Error = Simple<char>> {
+    return whitespace()
+        .then(just("regex"))
+        .ignore_then(characters_parser().delimited_by(just("(\""), just("\")")))
+        .try_map(|pattern, span| {
+            let pattern = pattern.to_string();
+            return match Regex::new(&pattern) {
+                Ok(_) => Ok(Comparison::Regex(pattern)),
+                Err(error) => Err(Simple::custom(span, format!("Invalid regex: {error}"))),
+            };
+        });
+}
+
+pub(crate) fn type_comparison_parser() -> impl Parser<char, Comparison, Error = Simple<char>> {
+    return whitespace()
+        .ignore_then(choice((
+            just("string").to(ValueType::String),
+            just("number").to(ValueType::Number),
+            just("integer").to(ValueType::Integer),
+            just("bool").to(ValueType::Boolean),
+            just("array").to(ValueType::Array),
+            just("object").to(ValueType::Object),
+        )))
+        .then_ignore(whitespace())
+        .map(Comparison::Type);
+}
+
+// Written as `range(1, 100)`, `approx(3.14, 0.01)`, `gt(0)`, `gte(0)`, `lt(10)` or `lte(10)`
+pub(crate) fn numeric_comparison_parser() -> impl Parser<char, Comparison, Error = Simple<char>> {
+    return whitespace()
+        .ignore_then(choice((
+            just("range")
+                .ignore_then(number_arguments_parser())
+                .map(|(min, max)| NumericComparison::Range(min, max)),
+            just("approx")
+                .ignore_then(number_arguments_parser())
+                .map(|(value, tolerance)| NumericComparison::Approx(value, tolerance)),
+            just("gte")
+                .ignore_then(number_argument_parser())
+                .map(NumericComparison::GreaterOrEqual),
+            just("gt")
+                .ignore_then(number_argument_parser())
+                .map(NumericComparison::GreaterThan),
+            just("lte")
+                .ignore_then(number_argument_parser())
+                .map(NumericComparison::LessOrEqual),
+            just("lt")
+                .ignore_then(number_argument_parser())
+                .map(NumericComparison::LessThan),
+        )))
+        .then_ignore(whitespace())
+        .map(Comparison::Numeric);
+}
+
+fn number_argument_parser() -> impl Parser<char, Number, Error = Simple<char>> {
+    return number_parser().padded().delimited_by(just('('), just(')'));
+}
+
+fn number_arguments_parser() -> impl Parser<char, (Number, Number), Error = Simple<char>> {
+    return number_parser()
+        .padded()
+        .then_ignore(just(','))
+        .then(number_parser().padded())
+        .delimited_by(just('('), just(')'));
+}
+
 pub(crate) fn guid_format_parser() -> impl Parser<char, Comparison, Error = Simple<char>> {
     return whitespace()
         .then(just("guid"))
@@ -1,5 +1,6 @@
 ﻿use crate::parser::body_parser;
-use crate::types::{CompositeString, HttpVerb};
+use crate::parser::body_parser::delimited_text_parser;
+use crate::types::{CompositeString, HttpVerb, QueryParameter};
 use chumsky::error::Simple;
 use chumsky::prelude::*;
 use chumsky::Parser;
@@ -23,3 +24,18 @@ pub(crate) fn url_parser() -> impl Parser<char, CompositeString, Error = Simple<
     let url = body_parser::characters_parser();
     return url;
 }
+
+/// Parses lines like `?page=1&size={{size}}` or `&sort=name` following the url
+pub(crate) fn query_parameters_parser(
+) -> impl Parser<char, Vec<QueryParameter>, Error = Simple<char>> {
+    let parameter = delimited_text_parser("=&")
+        .then(just('=').ignore_then(delimited_text_parser("&")).or_not())
+        .map(|(name, value)| QueryParameter { name, value });
+
+    return text::newline()
+        .ignore_then(one_of(" \t").repeated())
+        .ignore_then(one_of("?&"))
+        .ignore_then(parameter.separated_by(just('&')).at_least(1))
+        .repeated()
+        .flatten();
+}
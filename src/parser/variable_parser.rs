@@ -1,10 +1,12 @@
 ﻿use crate::parser::body_parser::{characters_parser, element_parser, value_parser};
 use crate::parser::snapshot_parser::{
-    guid_format_parser, ignore_comparison_parser, timestamp_format_parser,
+    guid_format_parser, ignore_comparison_parser, numeric_comparison_parser, regex_format_parser,
+    timestamp_format_parser, type_comparison_parser,
 };
 use crate::types::{
     Comparison, CompositeString, CompositeStringPart, Element, Generator, Value, Variable,
 };
+use chrono::format::{Item, StrftimeItems};
 use chumsky::error::Simple;
 use chumsky::prelude::*;
 use chumsky::text::Character;
@@ -20,7 +22,7 @@ pub(crate) fn variables_parser(
         .then_ignore(just('='))
         .then_ignore(filter(|c: &char| c.is_inline_whitespace()).repeated())
         .then(choice((
-            value_parser(element_parser(comparison)).map(|val| Variable::Value(val)),
+            value_parser(element_parser(comparison), comparison).map(|val| Variable::Value(val)),
             generator_parser().map(|generator| Variable::Generator(generator)),
         )))
         .padded()
@@ -41,12 +43,13 @@ pub(crate) fn variable_store_header_parser(
 {
     return just("{{").then(repeated_spaces()).ignore_then(
         text::ident()
-            .map(|name| Some(name))
-            .or(just("_").to(None))
+            // `_` is also an identifier, so it has to be told apart from a name here
+            .map(|name: String| (name != "_").then_some(name))
             .then_ignore(repeated_spaces().then(just(":").then(repeated_spaces())))
             .then(choice((
                 timestamp_format_parser(),
                 guid_format_parser(),
+                regex_format_parser(),
                 ignore_comparison_parser(),
             )))
             .then(
@@ -75,23 +78,26 @@ pub(crate) fn variable_store_body_parser(
 ) -> impl Parser<char, (Option<String>, (Value, Option<Comparison>)), Error = Simple<char>> {
     return just("{{").padded().ignore_then(
         text::ident()
-            .map(|name| Some(name))
-            .or(just("_").to(None))
+            // `_` is also an identifier, so it has to be told apart from a name here
+            .map(|name: String| (name != "_").then_some(name))
             .then_ignore(just(":").padded())
             .then(
                 choice((
                     timestamp_format_parser(),
                     guid_format_parser(),
+                    regex_format_parser(),
+                    type_comparison_parser(),
+                    numeric_comparison_parser(),
                     ignore_comparison_parser(),
                 ))
                 .then(
                     just(":")
                         .padded()
-                        .ignore_then(value_parser(element_parser.clone()))
+                        .ignore_then(value_parser(element_parser.clone(), true))
                         .or_not(),
                 )
                 .map(|(comparison, value)| (value.unwrap_or(Value::Null()), comparison))
-                .or(value_parser(element_parser).map(|value| (value, Comparison::Exact))),
+                .or(value_parser(element_parser, true).map(|value| (value, Comparison::Exact))),
             )
             .then_ignore(just("}}").padded())
             .map(|(variable_store, (value, comparison))| {
@@ -110,12 +116,95 @@ pub(crate) fn variable_name_string_parser() -> impl Parser<char, String, Error =
 }
 
 fn variable_reference_parser() -> impl Parser<char, String, Error = Simple<char>> {
-    return just("{{")
+    let process_variable = just("$env.")
         .ignore_then(text::ident())
+        .map(|name: String| format!("$env.{name}"));
+    return just("{{")
+        .ignore_then(process_variable.or(text::ident()))
         .then_ignore(just("}}"))
         .map(|name| name);
 }
 
 fn generator_parser() -> impl Parser<char, Generator, Error = Simple<char>> {
-    return just("gen(guid)").to(Generator::Guid);
+    let int = just("int")
+        .ignore_then(argument_parser(integer_parser()))
+        .then(argument_parser(integer_parser()))
+        .try_map(|(min, max), span| {
+            if min > max {
+                return Err(Simple::custom(
+                    span,
+                    format!("Invalid range: {min} is greater than {max}"),
+                ));
+            }
+            return Ok(Generator::Int(min, max));
+        });
+    let string = just("string")
+        .ignore_then(argument_parser(text::int(10)))
+        .try_map(|length: String, span| {
+            return length
+                .parse()
+                .map(Generator::String)
+                .map_err(|error| Simple::custom(span, format!("Invalid length: {error}")));
+        });
+    let format = characters_parser()
+        .delimited_by(just('"'), just('"'))
+        .try_map(|format, span| {
+            let format = format.to_string();
+            if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+                return Err(Simple::custom(span, format!("Invalid format: {format}")));
+            }
+            return Ok(format);
+        });
+    let now = just("now")
+        .ignore_then(time_offset_parser().or_not())
+        .then(argument_parser(format).or_not())
+        .map(|(offset, format)| Generator::Now(offset.unwrap_or(0), format));
+
+    return choice((
+        just("guid").to(Generator::Guid),
+        int,
+        string,
+        just("email").to(Generator::Email),
+        now,
+        just("seq").to(Generator::Sequence),
+    ))
+    .padded()
+    .delimited_by(just("gen("), just(')'));
+}
+
+fn argument_parser<T>(
+    parser: impl Parser<char, T, Error = Simple<char>>,
+) -> impl Parser<char, T, Error = Simple<char>> {
+    return just(',').padded().ignore_then(parser);
+}
+
+fn integer_parser() -> impl Parser<char, i64, Error = Simple<char>> {
+    return just('-').or_not().then(text::int(10)).try_map(
+        |(sign, digits): (Option<char>, String), span| {
+            let number = format!("{}{digits}", sign.map(String::from).unwrap_or_default());
+            return number
+                .parse()
+                .map_err(|error| Simple::custom(span, format!("Invalid integer: {error}")));
+        },
+    );
+}
+
+// An offset like `+1d` or `-30m`, converted to seconds
+fn time_offset_parser() -> impl Parser<char, i64, Error = Simple<char>> {
+    let unit = choice((
+        just('s').to(1),
+        just('m').to(60),
+        just('h').to(60 * 60),
+        just('d').to(24 * 60 * 60),
+    ));
+    return one_of("+-").then(text::int(10)).then(unit).try_map(
+        |((sign, amount), unit): ((char, String), i64), span| {
+            let amount = amount
+                .parse::<i64>()
+                .ok()
+                .and_then(|amount| amount.checked_mul(unit))
+                .ok_or_else(|| Simple::custom(span, "Invalid time offset"))?;
+            return Ok(if sign == '-' { -amount } else { amount });
+        },
+    );
 }
@@ -0,0 +1,239 @@
+use crate::client::HttpResponse;
+use crate::detector::Replacer;
+use crate::error::HttpSnapError;
+use crate::server::serve_connections;
+use crate::types::{Detector, RawInput, UpdateMode, UpdateOptions};
+use crate::{merger, parser};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::{Request, Response};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Method};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+#[derive(Debug, Clone)]
+pub struct RecordOptions {
+    /// Base url that requests are forwarded to, e.g. `http://localhost:5000`
+    pub target: String,
+    /// Directory the recorded files are written to
+    pub out: PathBuf,
+    /// Variable written in place of the target, so the files can run against other hosts
+    pub host_variable: Option<String>,
+}
+
+struct RecorderState {
+    options: RecordOptions,
+    client: Client,
+    replacer: Replacer,
+    next_index: Mutex<usize>,
+}
+
+// Connection specific headers that must not be forwarded by a proxy
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+/// Forwards every request to the target and writes each exchange into its own `.http` file
+pub async fn record(listener: TcpListener, options: RecordOptions) -> Result<(), HttpSnapError> {
+    std::fs::create_dir_all(&options.out)
+        .map_err(|error| HttpSnapError::io(&options.out, error))?;
+    let address = listener.local_addr().map_err(HttpSnapError::Server)?;
+    log::info!(
+        "Recording requests on http://{address} to {} into {}",
+        options.target,
+        options.out.display()
+    );
+
+    let client = Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .map_err(HttpSnapError::Transport)?;
+    let update_options = Some(UpdateOptions {
+        stop_on_failure: false,
+        update_mode: UpdateMode::Overwrite,
+        detectors: HashSet::from([Detector::Timestamp, Detector::Guid]),
+        body_file_threshold: None,
+    });
+    let state = Arc::new(RecorderState {
+        options,
+        client,
+        replacer: Replacer::new(&update_options),
+        next_index: Mutex::new(1),
+    });
+    return serve_connections(listener, state, forward).await;
+}
+
+async fn forward(
+    state: Arc<RecorderState>,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = request.method().to_string();
+    let path = match request.uri().path_and_query() {
+        Some(path_and_query) => path_and_query.to_string(),
+        None => String::from("/"),
+    };
+    // Responses are recorded as text, so compression is not requested
+    let headers = without_headers(request.headers(), &["host", "accept-encoding"]);
+    let body = match request.into_body().collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(_) => Bytes::new(),
+    };
+
+    let url = format!("{}{path}", state.options.target.trim_end_matches('/'));
+    let sent = state
+        .client
+        .request(
+            Method::from_bytes(method.as_bytes()).unwrap_or(Method::GET),
+            &url,
+        )
+        .headers(headers.clone())
+        .body(body.clone())
+        .send()
+        .await;
+    let response = match sent {
+        Ok(response) => response,
+        Err(error) => {
+            log::error!("{method} {path} could not be forwarded: {error}");
+            return Ok(Response::builder()
+                .status(502)
+                .body(Full::new(Bytes::from(error.to_string())))
+                .unwrap());
+        }
+    };
+
+    let status = response.status().as_u16();
+    let response_headers = response.headers().clone();
+    let response_body = response.bytes().await.unwrap_or_default();
+    log::info!("{method} {path} -> {status}");
+
+    let exchange = HttpResponse {
+        status,
+        headers: response_headers.clone(),
+        body: response_body.to_vec(),
+        request_url: url.clone(),
+        request_headers: headers,
+    };
+    if let Err(error) = write_exchange(&state, &method, &path, &body, &exchange).await {
+        log::warn!("{method} {path} was not recorded: {error}");
+    }
+
+    let mut builder = Response::builder().status(status);
+    for (name, value) in without_headers(&response_headers, &[]).iter() {
+        builder = builder.header(name, value);
+    }
+    return Ok(builder.body(Full::new(response_body)).unwrap());
+}
+
+async fn write_exchange(
+    state: &RecorderState,
+    method: &str,
+    path: &str,
+    body: &Bytes,
+    response: &HttpResponse,
+) -> Result<(), HttpSnapError> {
+    let host = match &state.options.host_variable {
+        Some(variable) => format!("{{{{{variable}}}}}"),
+        None => state.options.target.trim_end_matches('/').to_string(),
+    };
+    let mut text = format!("{method} {host}{path}\n");
+    for (name, value) in response.request_headers.iter() {
+        text += &format!("{name}: {}\n", String::from_utf8_lossy(value.as_bytes()));
+    }
+    if !body.is_empty() {
+        text += "\n";
+        text += &format_request_body(&response.request_headers, body)?;
+    }
+
+    let parsed_response = parser::parse_response(response).await?;
+    let snapshot = state.replacer.detect_types(parsed_response);
+
+    let mut next_index = state.next_index.lock().unwrap();
+    let file_path = loop {
+        let name = format!(
+            "{:03}_{}_{}.http",
+            *next_index,
+            method.to_lowercase(),
+            slug(path)
+        );
+        *next_index += 1;
+        let candidate = state.options.out.join(name);
+        if !candidate.exists() {
+            break candidate;
+        }
+    };
+
+    let raw_input = RawInput {
+        text,
+        section: 0,
+        imported_path: None,
+        path: file_path.clone(),
+        line_offset: 0,
+        column_offset: 0,
+        name: None,
+        heading: None,
+    };
+    let content =
+        merger::create_content_with_snapshot(&raw_input, &snapshot, &UpdateMode::Overwrite)?;
+    std::fs::write(&file_path, content).map_err(|error| HttpSnapError::SnapshotWrite {
+        path: file_path.clone(),
+        reason: error.to_string(),
+    })?;
+    log::info!("Recorded {}", file_path.display());
+    return Ok(());
+}
+
+// Bodies without a JSON content type are written as they were sent
+fn format_request_body(headers: &HeaderMap, body: &Bytes) -> Result<String, HttpSnapError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_lowercase());
+    return match content_type {
+        Some(content_type) if !content_type.contains("json") => String::from_utf8(body.to_vec())
+            .map_err(|_| HttpSnapError::InvalidBody(String::from("Request body is not text"))),
+        _ => {
+            let json = serde_json::from_slice::<serde_json::Value>(body).map_err(|_| {
+                HttpSnapError::InvalidBody(String::from("Request body is not JSON"))
+            })?;
+            Ok(serde_json::to_string_pretty(&json).unwrap())
+        }
+    };
+}
+
+fn without_headers(headers: &HeaderMap, excluded: &[&str]) -> HeaderMap {
+    let mut kept = HeaderMap::new();
+    for (name, value) in headers.iter() {
+        let name_str = name.as_str();
+        if !HOP_BY_HOP_HEADERS.contains(&name_str) && !excluded.contains(&name_str) {
+            kept.append(name.clone(), value.clone());
+        }
+    }
+    return kept;
+}
+
+// Turns `/api/items/1?done=true` into `api_items_1`
+fn slug(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or_default();
+    let slug = path
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    return if slug.is_empty() {
+        String::from("root")
+    } else {
+        slug.chars().take(60).collect()
+    };
+}
@@ -0,0 +1,197 @@
+use crate::diff::render_unified_diff;
+use crate::error::HttpSnapError;
+use crate::types::{FileReport, RequestReport};
+use itertools::Itertools;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Bumped whenever a field of the JSON report is renamed or removed
+const JSON_REPORT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    version: u32,
+    total: usize,
+    passed: usize,
+    failed: usize,
+    files: Vec<JsonFileReport<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFileReport<'a> {
+    path: &'a PathBuf,
+    passed: bool,
+    /// Set when the file could not be run, for example because it failed to parse
+    error: Option<String>,
+    requests: &'a [RequestReport],
+}
+
+/// Creates a JSON report describing every file and every request that was run
+pub fn create_json_report(
+    results: &[(PathBuf, Result<FileReport, HttpSnapError>)],
+) -> Result<String, serde_json::Error> {
+    let files = results
+        .iter()
+        .map(|(path, result)| match result {
+            Ok(report) => JsonFileReport {
+                path,
+                passed: report.passed(),
+                error: None,
+                requests: &report.requests,
+            },
+            Err(error) => JsonFileReport {
+                path,
+                passed: false,
+                error: Some(error.to_string()),
+                requests: &[],
+            },
+        })
+        .collect_vec();
+
+    let passed = files.iter().filter(|file| file.passed).count();
+    let report = JsonReport {
+        version: JSON_REPORT_VERSION,
+        total: files.len(),
+        passed,
+        failed: files.len() - passed,
+        files,
+    };
+    return serde_json::to_string_pretty(&report);
+}
+
+/// Creates a JUnit XML report with a testsuite per file and a testcase per request.
+/// Files that could not be run are reported as a single testcase with an error.
+pub fn create_junit_report(results: &[(PathBuf, Result<FileReport, HttpSnapError>)]) -> String {
+    let suites = results
+        .iter()
+        .map(|(path, result)| match result {
+            Ok(report) => create_testsuite(report),
+            Err(error) => create_errored_testsuite(path, error),
+        })
+        .collect_vec();
+
+    let tests = suites.iter().map(|suite| suite.tests).sum::<usize>();
+    let failures = suites.iter().map(|suite| suite.failures).sum::<usize>();
+    let errors = suites.iter().map(|suite| suite.errors).sum::<usize>();
+    let time = suites.iter().map(|suite| suite.time).sum::<Duration>();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml += &format!(
+        "<testsuites name=\"http-snap\" tests=\"{tests}\" failures=\"{failures}\" errors=\"{errors}\" time=\"{}\">\n",
+        format_time(&time)
+    );
+    for suite in suites {
+        xml += &suite.xml;
+    }
+    xml += "</testsuites>\n";
+    return xml;
+}
+
+struct Testsuite {
+    tests: usize,
+    failures: usize,
+    errors: usize,
+    time: Duration,
+    xml: String,
+}
+
+fn create_testsuite(report: &FileReport) -> Testsuite {
+    let name = escape(&report.path.display().to_string());
+    let failures = report
+        .requests
+        .iter()
+        .filter(|request| request.matched_option.is_none())
+        .count();
+    let time = report.requests.iter().map(|request| request.duration).sum();
+
+    let mut xml = format!(
+        "  <testsuite name=\"{name}\" tests=\"{}\" failures=\"{failures}\" errors=\"0\" time=\"{}\">\n",
+        report.requests.len(),
+        format_time(&time)
+    );
+    for request in &report.requests {
+        xml += &create_testcase(&name, request);
+    }
+    xml += "  </testsuite>\n";
+
+    return Testsuite {
+        tests: report.requests.len(),
+        failures,
+        errors: 0,
+        time,
+        xml,
+    };
+}
+
+fn create_testcase(classname: &str, request: &RequestReport) -> String {
+    let name = match &request.name {
+        Some(name) => name.clone(),
+        None => format!("Request {}", request.index + 1),
+    };
+    let opening = format!(
+        "    <testcase name=\"{}\" classname=\"{classname}\" time=\"{}\"",
+        escape(&name),
+        format_time(&request.duration)
+    );
+    if request.matched_option.is_some() {
+        return opening + "/>\n";
+    }
+
+    let mut details = request
+        .mismatches
+        .iter()
+        .map(|mismatch| mismatch.to_string())
+        .join("\n");
+    if let Some(diff) = &request.diff {
+        details += "\n\n";
+        details += &render_unified_diff(diff, false);
+    }
+    let message = match request.mismatches.len() {
+        1 => String::from("1 mismatch"),
+        count => format!("{count} mismatches"),
+    };
+    return format!(
+        "{opening}>\n      <failure message=\"{message}\" type=\"SnapshotMismatch\">{}</failure>\n    </testcase>\n",
+        escape(&details)
+    );
+}
+
+fn create_errored_testsuite(path: &PathBuf, error: &HttpSnapError) -> Testsuite {
+    let name = escape(&path.display().to_string());
+    let message = error.to_string();
+    let first_line = message.lines().next().unwrap_or_default();
+    let xml = format!(
+        "  <testsuite name=\"{name}\" tests=\"1\" failures=\"0\" errors=\"1\" time=\"0.000\">\n    <testcase name=\"{name}\" classname=\"{name}\" time=\"0.000\">\n      <error message=\"{}\" type=\"Error\">{}</error>\n    </testcase>\n  </testsuite>\n",
+        escape(first_line),
+        escape(&message)
+    );
+
+    return Testsuite {
+        tests: 1,
+        failures: 0,
+        errors: 1,
+        time: Duration::ZERO,
+        xml,
+    };
+}
+
+fn format_time(duration: &Duration) -> String {
+    return format!("{:.3}", duration.as_secs_f64());
+}
+
+fn escape(text: &str) -> String {
+    return text
+        .chars()
+        .map(|c| match c {
+            '&' => String::from("&amp;"),
+            '<' => String::from("&lt;"),
+            '>' => String::from("&gt;"),
+            '"' => String::from("&quot;"),
+            '\'' => String::from("&apos;"),
+            // Control characters other than tab and newlines are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => String::new(),
+            c => c.to_string(),
+        })
+        .collect();
+}
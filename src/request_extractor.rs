@@ -1,48 +1,73 @@
-﻿use crate::types::RawInput;
+﻿use crate::diagnostics::line_of;
+use crate::error::HttpSnapError;
+use crate::types::RawInput;
 use itertools::Itertools;
 use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
 use std::ffi::OsStr;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-pub fn extract_requests(path_to_file: &PathBuf) -> Vec<RawInput> {
+pub fn extract_requests(path_to_file: &PathBuf) -> Result<Vec<RawInput>, HttpSnapError> {
     let extension = path_to_file.extension().unwrap_or(OsStr::new(""));
     if extension == "http" {
         return extract_from_http_file(path_to_file);
     } else if extension == "md" {
         return extract_from_markdown(path_to_file);
     } else {
-        panic!("Unknown to handle file {:?}", path_to_file);
+        return Err(HttpSnapError::UnsupportedFile(path_to_file.clone()));
     }
 }
 
-fn extract_from_http_file(path_to_file: &PathBuf) -> Vec<RawInput> {
-    let raw_text = read_to_string(path_to_file).unwrap();
+fn extract_from_http_file(path_to_file: &PathBuf) -> Result<Vec<RawInput>, HttpSnapError> {
+    let raw_text =
+        read_to_string(path_to_file).map_err(|error| HttpSnapError::io(path_to_file, error))?;
     let text = raw_text.trim_start_matches("\u{feff}");
-    return extract_from_text(path_to_file, 0, text);
+    return extract_from_text(path_to_file, 0, 0, text);
 }
 
-fn extract_from_markdown(path_to_file: &PathBuf) -> Vec<RawInput> {
-    let raw_text = read_to_string(path_to_file)
-        .unwrap_or_else(|_| panic!("Could not read file {}", path_to_file.display()));
+fn extract_from_markdown(path_to_file: &PathBuf) -> Result<Vec<RawInput>, HttpSnapError> {
+    let raw_text =
+        read_to_string(path_to_file).map_err(|error| HttpSnapError::io(path_to_file, error))?;
     let text = raw_text.trim_start_matches("\u{feff}");
     let sections = extract_http_section_from_markdown(text);
 
     let mut request_texts = Vec::new();
     for (index, section) in sections.iter().enumerate() {
-        let mut section_requests = extract_from_text(&path_to_file, index, section);
+        let mut section_requests =
+            extract_from_text(&path_to_file, index, section.line_offset, &section.content)?;
+        for request in &mut section_requests {
+            if request.imported_path.is_none() {
+                request.heading = section.heading.clone();
+            }
+        }
         request_texts.append(&mut section_requests);
     }
-    return request_texts;
+    return Ok(request_texts);
 }
 
-fn extract_http_section_from_markdown(text: &str) -> Vec<String> {
+struct MarkdownSection {
+    content: String,
+    line_offset: usize,
+    heading: Option<String>,
+}
+
+// Returns the content of each http code block, the line it starts on and the heading above it
+fn extract_http_section_from_markdown(text: &str) -> Vec<MarkdownSection> {
     let mut sections = Vec::new();
     let mut in_http = false;
     let mut content = String::new();
+    let mut line_offset = None;
+    let mut in_heading = false;
+    let mut heading = String::new();
 
-    for event in Parser::new(text) {
+    for (event, range) in Parser::new(text).into_offset_iter() {
         match event {
+            Event::Start(Tag::Heading { .. }) => {
+                in_heading = true;
+                heading.clear();
+            }
+            Event::End(TagEnd::Heading(_)) => in_heading = false,
+            Event::Text(title) | Event::Code(title) if in_heading => heading.push_str(&title),
             Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
                 if lang.eq_ignore_ascii_case("http") =>
             {
@@ -50,11 +75,19 @@ fn extract_http_section_from_markdown(text: &str) -> Vec<String> {
             }
             Event::End(TagEnd::CodeBlock) => {
                 in_http = false;
-                sections.push(content.clone());
+                sections.push(MarkdownSection {
+                    content: content.clone(),
+                    line_offset: line_offset.unwrap_or(0),
+                    heading: Some(heading.trim().to_string()).filter(|h| !h.is_empty()),
+                });
                 content.clear();
+                line_offset = None;
             }
-            Event::Text(text) if in_http => {
-                content.push_str(&text);
+            Event::Text(code) if in_http => {
+                if line_offset.is_none() {
+                    line_offset = Some(line_of(text, range.start));
+                }
+                content.push_str(&code);
             }
             _ => {}
         }
@@ -63,36 +96,62 @@ fn extract_http_section_from_markdown(text: &str) -> Vec<String> {
     return sections;
 }
 
-fn extract_from_text(path_to_file: &PathBuf, section_number: usize, text: &str) -> Vec<RawInput> {
+fn extract_from_text(
+    path_to_file: &PathBuf,
+    section_number: usize,
+    line_offset: usize,
+    text: &str,
+) -> Result<Vec<RawInput>, HttpSnapError> {
     let mut request_texts = Vec::new();
-    let (files_to_import, text_without_imports) = extract_imports(&text);
+    let (files_to_import, import_lines, text_without_imports) = extract_imports(&text);
     for file in files_to_import {
         let base_dir = path_to_file.parent().unwrap_or_else(|| Path::new(""));
         let full_path = base_dir.join(&file);
 
-        let imported_requests = extract_requests(&full_path);
+        let imported_requests = extract_requests(&full_path)?;
 
         for request in imported_requests {
             request_texts.push(RawInput {
                 text: request.text,
                 section: request.section,
                 imported_path: Some(PathBuf::from(&file)),
+                path: request.path,
+                line_offset: request.line_offset,
+                column_offset: request.column_offset,
+                name: request.name,
+                heading: request.heading,
             })
         }
     }
 
-    for request in text_without_imports.split("###") {
+    let mut request_line = line_offset + import_lines;
+    for (index, request) in text_without_imports.split("###").enumerate() {
+        // Everything after ### on the same line names the request
+        let name_length = match index {
+            0 => 0,
+            _ => request.find('\n').map_or(request.len(), |end| end + 1),
+        };
+        let (name, content) = request.split_at(name_length);
+        let trimmed = &content[..content.len() - content.trim_start().len()];
+        let leading_whitespace = name_length + trimmed.len();
+        let indentation = trimmed.rsplit('\n').next().unwrap_or_default();
         request_texts.push(RawInput {
-            text: request.trim().to_string(),
+            text: content.trim().to_string(),
             section: section_number,
             imported_path: None,
-        })
+            path: path_to_file.clone(),
+            line_offset: request_line + line_of(request, leading_whitespace),
+            column_offset: indentation.chars().count(),
+            name: Some(name.trim().to_string()).filter(|name| !name.is_empty()),
+            heading: None,
+        });
+        request_line += line_of(request, request.len());
     }
 
-    return request_texts;
+    return Ok(request_texts);
 }
 
-fn extract_imports(text: &str) -> (Vec<String>, String) {
+fn extract_imports(text: &str) -> (Vec<String>, usize, String) {
     let mut imports = Vec::new();
     let mut index = 0;
     for line in text.lines() {
@@ -105,5 +164,5 @@ fn extract_imports(text: &str) -> (Vec<String>, String) {
         }
         index += 1;
     }
-    return (imports, text.lines().skip(index).join("\n"));
+    return (imports, index, text.lines().skip(index).join("\n"));
 }
@@ -0,0 +1,474 @@
+use crate::diagnostics::ParseError;
+use crate::error::HttpSnapError;
+use crate::types::{
+    Array, Comparison, CompositeString, CompositeStringPart, Element, Header, HttpVerb, Number,
+    NumericComparison, RequestBody, ResponseBody, Snapshot, Status, Value, ValueType, Variable,
+};
+use crate::{parser, request_extractor};
+use chrono::format::{Item, StrftimeItems};
+use chrono::Utc;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+/// A recorded request and the snapshot that is replayed when it is matched
+#[derive(Debug)]
+pub struct Route {
+    pub source: PathBuf,
+    pub verb: HttpVerb,
+    pub path: Vec<PathPart>,
+    pub body: Option<RequestBody>,
+    pub snapshot: Snapshot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathPart {
+    Literal(String),
+    /// A variable that could not be resolved, which matches a single path segment
+    Wildcard,
+}
+
+struct MockState {
+    routes: Vec<Route>,
+    served: Mutex<Vec<bool>>,
+    match_body: bool,
+}
+
+/// Reads the requests of every file and keeps those with a snapshot as routes.
+/// Variables in urls are resolved from the environment and the file's own variables.
+pub fn load_routes(
+    paths: &Vec<PathBuf>,
+    environment_variables: &HashMap<String, Value>,
+) -> Result<Vec<Route>, HttpSnapError> {
+    let mut routes = Vec::new();
+    for path in paths {
+        for request in request_extractor::extract_requests(path)? {
+            let to_error = |errors| {
+                ParseError::new(
+                    &request.path,
+                    request.line_offset,
+                    request.column_offset,
+                    &request.text,
+                    errors,
+                )
+            };
+            if parser::try_parse_delay(&request.text)
+                .map_err(to_error)?
+                .is_some()
+            {
+                continue;
+            }
+
+            let http_file = parser::parse_file(&request.text).map_err(to_error)?;
+            let http_file = parser::load_file_references(http_file, &request.path)?;
+            let mut variables = environment_variables.clone();
+            for (name, variable) in http_file.variables {
+                if let Variable::Value(value) = variable {
+                    variables.insert(name, value);
+                }
+            }
+
+            // Only the first option is replayed when there are several
+            if let Some(snapshot) = http_file.snapshots.into_iter().next() {
+                validate_timestamp_formats(&snapshot)?;
+                routes.push(Route {
+                    source: request.path.clone(),
+                    verb: http_file.verb,
+                    path: to_path_parts(&http_file.url, &variables),
+                    body: http_file.body,
+                    snapshot,
+                });
+            }
+        }
+    }
+    return Ok(routes);
+}
+
+// Timestamps are generated with their format, so it must be valid before serving
+fn validate_timestamp_formats(snapshot: &Snapshot) -> Result<(), HttpSnapError> {
+    for header in &snapshot.headers {
+        if let Some(Comparison::TimestampFormat(pattern)) = &header.comparison {
+            if !is_valid_timestamp_format(&pattern.to_string()) {
+                return Err(HttpSnapError::InvalidHeader {
+                    name: header.name.clone(),
+                    reason: format!("timestamp format \"{pattern}\" is invalid"),
+                });
+            }
+        }
+    }
+    if let Some(ResponseBody::Json(json)) = &snapshot.body {
+        return validate_element_timestamp_formats(&json.element);
+    }
+    return Ok(());
+}
+
+fn validate_element_timestamp_formats(element: &Element) -> Result<(), HttpSnapError> {
+    if let Some(Comparison::TimestampFormat(pattern)) = &element.comparison {
+        if !is_valid_timestamp_format(&pattern.to_string()) {
+            let reason = format!("timestamp format \"{pattern}\" is invalid");
+            return Err(HttpSnapError::InvalidBody(reason));
+        }
+    }
+    let children = match &element.value {
+        Value::Object(object) => object
+            .members
+            .iter()
+            .map(|member| member.value.clone())
+            .collect(),
+        Value::Array(array) => array.get_known_elements().unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    for child in &children {
+        validate_element_timestamp_formats(child)?;
+    }
+    return Ok(());
+}
+
+fn is_valid_timestamp_format(format: &str) -> bool {
+    return !StrftimeItems::new(format).any(|item| item == Item::Error);
+}
+
+/// Serves the routes on the listener until the process is stopped.
+/// Routes that match the same request are replayed in order, repeating the last one.
+pub async fn serve(
+    listener: TcpListener,
+    routes: Vec<Route>,
+    match_body: bool,
+) -> Result<(), HttpSnapError> {
+    let address = listener.local_addr().map_err(HttpSnapError::Server)?;
+    log::info!("Serving {} snapshots on http://{address}", routes.len());
+
+    let state = Arc::new(MockState {
+        served: Mutex::new(vec![false; routes.len()]),
+        routes,
+        match_body,
+    });
+    return serve_connections(listener, state, respond).await;
+}
+
+/// Accepts connections until the process is stopped, handling each request with `handle`
+pub(crate) async fn serve_connections<S, F, Fut>(
+    listener: TcpListener,
+    state: Arc<S>,
+    handle: F,
+) -> Result<(), HttpSnapError>
+where
+    S: Send + Sync + 'static,
+    F: Fn(Arc<S>, Request<Incoming>) -> Fut + Copy + Send + Sync + 'static,
+    Fut: Future<Output = Result<Response<Full<Bytes>>, Infallible>> + Send + 'static,
+{
+    loop {
+        let (stream, _) = listener.accept().await.map_err(HttpSnapError::Server)?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(|request| handle(state.clone(), request));
+            let connection = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            if let Err(error) = connection.await {
+                log::error!("Connection failed: {error}");
+            }
+        });
+    }
+}
+
+async fn respond(
+    state: Arc<MockState>,
+    request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let body = match request.into_body().collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(_) => Bytes::new(),
+    };
+
+    let candidates = state
+        .routes
+        .iter()
+        .enumerate()
+        .filter(|(_, route)| route.verb.to_string() == method)
+        .filter(|(_, route)| path_matches(&route.path, &path))
+        .filter(|(_, route)| !state.match_body || body_matches(&route.body, &body))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+
+    let Some(index) = pick_route(&state, &candidates) else {
+        log::warn!("{method} {path} -> no snapshot found");
+        let response = Response::builder()
+            .status(404)
+            .body(Full::new(Bytes::from(format!(
+                "No snapshot found for {method} {path}"
+            ))))
+            .unwrap();
+        return Ok(response);
+    };
+
+    let route = &state.routes[index];
+    let status = to_status_code(&route.snapshot.status);
+    log::info!(
+        "{method} {path} -> {status} from {}",
+        route.source.display()
+    );
+
+    let mut builder = Response::builder().status(status);
+    for header in &route.snapshot.headers {
+        if let Some(value) = generate_header_value(header) {
+            builder = builder.header(&header.name, value);
+        }
+    }
+    let body = match &route.snapshot.body {
+        Some(ResponseBody::Json(json)) => generate_json(&json.element).to_string(),
+        Some(ResponseBody::Text(text)) => text.clone(),
+        Some(ResponseBody::Binary { .. }) => {
+            let source = route.source.display();
+            log::warn!("Snapshot from {source} only has the hash of a binary body to replay");
+            String::new()
+        }
+        None => String::new(),
+    };
+    let response = builder
+        .body(Full::new(Bytes::from(body)))
+        .unwrap_or_else(|error| {
+            let source = route.source.display();
+            log::error!("Snapshot from {source} is not a valid response: {error}");
+            Response::builder()
+                .status(500)
+                .body(Full::new(Bytes::new()))
+                .unwrap()
+        });
+    return Ok(response);
+}
+
+fn pick_route(state: &MockState, candidates: &Vec<usize>) -> Option<usize> {
+    let mut served = state.served.lock().unwrap();
+    let index = candidates
+        .iter()
+        .find(|index| !served[**index])
+        .or(candidates.last())
+        .copied()?;
+    served[index] = true;
+    return Some(index);
+}
+
+// Keeps the path of the url, dropping a leading host variable or scheme and authority
+fn to_path_parts(url: &CompositeString, variables: &HashMap<String, Value>) -> Vec<PathPart> {
+    let mut parts = Vec::new();
+    for (index, part) in url.parts.iter().enumerate() {
+        match part {
+            CompositeStringPart::Literal(text) => parts.push(PathPart::Literal(text.clone())),
+            CompositeStringPart::VariableName(name) => match variables.get(name) {
+                Some(Value::String(value)) => parts.push(PathPart::Literal(value.to_string())),
+                Some(Value::Number(value)) => parts.push(PathPart::Literal(value.to_string())),
+                _ if index == 0 => continue,
+                _ => parts.push(PathPart::Wildcard),
+            },
+        }
+    }
+
+    let mut merged: Vec<PathPart> = Vec::new();
+    for part in parts {
+        match (merged.last_mut(), part) {
+            (Some(PathPart::Literal(last)), PathPart::Literal(text)) => last.push_str(&text),
+            (_, part) => merged.push(part),
+        }
+    }
+
+    if let Some(PathPart::Literal(first)) = merged.first_mut() {
+        if let Some(scheme_end) = first.find("://") {
+            let authority = &first[scheme_end + 3..];
+            *first = authority[authority.find('/').unwrap_or(authority.len())..].to_string();
+        }
+    }
+    if let Some(PathPart::Literal(last)) = merged.last_mut() {
+        if let Some(query_start) = last.find('?') {
+            last.truncate(query_start);
+        }
+    }
+    return merged;
+}
+
+fn path_matches(parts: &[PathPart], path: &str) -> bool {
+    return match parts.split_first() {
+        None => path.is_empty() || path == "/",
+        Some((PathPart::Literal(literal), rest)) => match path.strip_prefix(literal.as_str()) {
+            Some(remaining) => path_matches(rest, remaining),
+            None => false,
+        },
+        Some((PathPart::Wildcard, rest)) => {
+            let segment_end = path.find('/').unwrap_or(path.len());
+            (1..=segment_end).any(|end| path_matches(rest, &path[end..]))
+        }
+    };
+}
+
+// Variable references in the recorded body match any value
+fn body_matches(expected: &Option<RequestBody>, actual: &Bytes) -> bool {
+    return match expected {
+        None => actual.is_empty(),
+        Some(RequestBody::Json(json)) => serde_json::from_slice::<serde_json::Value>(actual)
+            .is_ok_and(|value| element_matches(&json.element, &value)),
+        Some(RequestBody::Form(fields)) => {
+            let actual_fields = form_urlencoded::parse(actual).collect::<Vec<_>>();
+            fields.len() == actual_fields.len()
+                && fields
+                    .iter()
+                    .zip(&actual_fields)
+                    .all(|(field, (name, value))| {
+                        text_matches(&field.name, name) && text_matches(&field.value, value)
+                    })
+        }
+        Some(RequestBody::Raw(text)) => text_matches(text, &String::from_utf8_lossy(actual)),
+        Some(RequestBody::File(path)) => {
+            std::fs::read(path).is_ok_and(|content| content == *actual)
+        }
+        // The boundary is chosen by the client, so the parts are not compared
+        Some(RequestBody::Multipart(_)) => !actual.is_empty(),
+    };
+}
+
+fn text_matches(expected: &CompositeString, actual: &str) -> bool {
+    let has_variable = expected
+        .parts
+        .iter()
+        .any(|part| matches!(part, CompositeStringPart::VariableName(_)));
+    return has_variable || expected.to_string() == actual;
+}
+
+fn element_matches(expected: &Element, actual: &serde_json::Value) -> bool {
+    return match (&expected.value, actual) {
+        (Value::VariableReference(_), _) => true,
+        (Value::Object(object), serde_json::Value::Object(members)) => {
+            object.members.len() == members.len()
+                && object.members.iter().all(|member| {
+                    members
+                        .get(&member.key)
+                        .is_some_and(|value| element_matches(&member.value, value))
+                })
+        }
+        (Value::Array(Array::VariableReference(_)), serde_json::Value::Array(_)) => true,
+        (Value::Array(array), serde_json::Value::Array(values)) => {
+            match array.get_known_elements() {
+                Ok(elements) => {
+                    elements.len() == values.len()
+                        && elements
+                            .iter()
+                            .zip(values)
+                            .all(|(element, value)| element_matches(element, value))
+                }
+                Err(_) => false,
+            }
+        }
+        (Value::String(text), serde_json::Value::String(value)) => text_matches(text, value),
+        (Value::Number(number), serde_json::Value::Number(value)) => {
+            serde_json::from_str::<serde_json::Number>(&number.to_string())
+                .is_ok_and(|number| number.as_f64() == value.as_f64())
+        }
+        (Value::Boolean(boolean), serde_json::Value::Bool(value)) => boolean == value,
+        (Value::Null(), serde_json::Value::Null) => true,
+        _ => false,
+    };
+}
+
+fn to_status_code(status: &Status) -> u16 {
+    let code = match status {
+        Status::Value(value) => value.to_string(),
+        Status::Pattern(pattern) => pattern.replace(['x', 'X'], "0"),
+    };
+    return code.parse().unwrap_or(200);
+}
+
+fn generate_header_value(header: &Header) -> Option<String> {
+    // The length is set from the generated body instead
+    if header.name.eq_ignore_ascii_case("content-length") {
+        return None;
+    }
+
+    return match &header.comparison {
+        Some(Comparison::Guid) => Some(Uuid::new_v4().to_string()),
+        Some(Comparison::TimestampFormat(pattern)) => {
+            Some(Utc::now().format(&pattern.to_string()).to_string())
+        }
+        Some(Comparison::Ignore) if header.value.parts.is_empty() => None,
+        _ => Some(header.value.to_string()),
+    };
+}
+
+fn generate_json(element: &Element) -> serde_json::Value {
+    match &element.comparison {
+        Some(Comparison::Guid) => return serde_json::Value::from(Uuid::new_v4().to_string()),
+        Some(Comparison::TimestampFormat(pattern)) => {
+            let timestamp = Utc::now().format(&pattern.to_string()).to_string();
+            return serde_json::Value::from(timestamp);
+        }
+        // Without an example value, a value is made up that satisfies the comparison
+        Some(Comparison::Type(value_type)) if matches!(element.value, Value::Null()) => {
+            return match value_type {
+                ValueType::String => serde_json::Value::from(""),
+                ValueType::Number | ValueType::Integer => serde_json::Value::from(0),
+                ValueType::Boolean => serde_json::Value::from(false),
+                ValueType::Array => serde_json::Value::Array(Vec::new()),
+                ValueType::Object => serde_json::Value::Object(serde_json::Map::new()),
+            };
+        }
+        Some(Comparison::Numeric(comparison)) if matches!(element.value, Value::Null()) => {
+            return match comparison {
+                NumericComparison::Range(value, _)
+                | NumericComparison::Approx(value, _)
+                | NumericComparison::GreaterOrEqual(value)
+                | NumericComparison::LessOrEqual(value) => generate_number(value),
+                NumericComparison::GreaterThan(bound) => generate_number_next_to(bound, 1),
+                NumericComparison::LessThan(bound) => generate_number_next_to(bound, -1),
+            };
+        }
+        _ => (),
+    }
+
+    return match &element.value {
+        Value::Object(object) => serde_json::Value::Object(
+            object
+                .members
+                .iter()
+                .map(|member| (member.key.clone(), generate_json(&member.value)))
+                .collect(),
+        ),
+        Value::Array(array) => serde_json::Value::Array(
+            array
+                .get_known_elements()
+                .unwrap_or_default()
+                .iter()
+                .map(generate_json)
+                .collect(),
+        ),
+        Value::String(text) => serde_json::Value::from(text.to_string()),
+        Value::Number(number) => generate_number(number),
+        Value::Boolean(boolean) => serde_json::Value::from(*boolean),
+        Value::VariableReference(_) | Value::Null() => serde_json::Value::Null,
+    };
+}
+
+fn generate_number(number: &Number) -> serde_json::Value {
+    return match number {
+        Number::Int(number) => serde_json::Value::from(*number),
+        _ => serde_json::from_str(&number.to_string()).unwrap_or(serde_json::Value::Null),
+    };
+}
+
+// A number just past the bound, in the direction of the step
+fn generate_number_next_to(bound: &Number, step: i64) -> serde_json::Value {
+    return match bound {
+        Number::Int(bound) => match bound.checked_add(step) {
+            Some(number) => serde_json::Value::from(number),
+            None => serde_json::Value::from(*bound as f64 + step as f64),
+        },
+        _ => serde_json::Value::from(bound.as_f64() + step as f64),
+    };
+}
@@ -1,14 +1,19 @@
-﻿use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+﻿use crate::error::HttpSnapError;
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
 use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ExecuteOptions {
     pub mode: Mode,
     pub update_options: Option<UpdateOptions>,
+    pub compare_options: CompareOptions,
+    /// Seed for the generators, so a run can be replayed with the same generated values
+    pub seed: Option<u64>,
 }
 
 impl ExecuteOptions {
@@ -16,6 +21,8 @@ impl ExecuteOptions {
         return Self {
             mode: Mode::Test,
             update_options: None,
+            compare_options: CompareOptions::default(),
+            seed: None,
         };
     }
 
@@ -30,11 +37,20 @@ impl ExecuteOptions {
                 stop_on_failure,
                 update_mode,
                 detectors: detectors.iter().cloned().collect(),
+                body_file_threshold: None,
             }),
+            compare_options: CompareOptions::default(),
+            seed: None,
         };
     }
 }
 
+#[derive(Debug, Default)]
+pub struct CompareOptions {
+    /// Compares numbers by value, so `1`, `1.0` and `1.0e0` are equal
+    pub numeric_equality: bool,
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum Mode {
     Test,
@@ -46,6 +62,8 @@ pub struct UpdateOptions {
     pub stop_on_failure: bool,
     pub update_mode: UpdateMode,
     pub detectors: HashSet<Detector>,
+    /// Bodies with more lines than this are written to a separate file referenced with `<<<`
+    pub body_file_threshold: Option<usize>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -89,6 +107,14 @@ pub struct RawInput {
     pub text: String,
     pub section: usize,
     pub imported_path: Option<PathBuf>,
+    pub path: PathBuf,
+    pub line_offset: usize,
+    /// Columns of indentation trimmed from the first line of `text`
+    pub column_offset: usize,
+    /// Name given after `###`, which is kept when snapshots are written
+    pub name: Option<String>,
+    /// Heading above the code block when the request comes from a markdown file
+    pub heading: Option<String>,
 }
 
 #[derive(Debug)]
@@ -96,9 +122,95 @@ pub struct HttpFile {
     pub variables: HashMap<String, Variable>,
     pub verb: HttpVerb,
     pub url: CompositeString,
+    /// Parameters from `?` and `&` lines below the url, which are encoded before sending
+    pub query_parameters: Vec<QueryParameter>,
     pub headers: Vec<Header>,
-    pub body: Option<Json>,
+    pub body: Option<RequestBody>,
     pub snapshots: Vec<Snapshot>,
+    /// Lines below `CAPTURE`, evaluated when the response matches a snapshot
+    pub captures: Vec<Capture>,
+}
+
+/// Written as `@order_id = $.items[0].id`, `@order_id = /items/0/id` or `@etag = header(ETag)`
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub name: String,
+    pub source: CaptureSource,
+}
+
+#[derive(Debug, Clone)]
+pub enum CaptureSource {
+    Body(Vec<PathSegment>),
+    Header(String),
+}
+
+/// Step into a JSON body. A key also selects an array element when it is a number,
+/// since JSON pointers do not tell the two apart.
+#[derive(Debug, Clone)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl Display for CaptureSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureSource::Body(segments) => {
+                write!(f, "$")?;
+                for segment in segments {
+                    match segment {
+                        PathSegment::Key(key) => {
+                            write!(f, "[{}]", serde_json::to_string(key).unwrap())?
+                        }
+                        PathSegment::Index(index) => write!(f, "[{index}]")?,
+                    }
+                }
+                Ok(())
+            }
+            CaptureSource::Header(name) => write!(f, "header({name})"),
+        }
+    }
+}
+
+/// Body of a request, in the format given by its `Content-Type` header
+#[derive(Debug, Clone)]
+pub enum RequestBody {
+    Json(Json),
+    /// Fields of an `application/x-www-form-urlencoded` body
+    Form(Vec<FormField>),
+    /// Text that is sent as written, for example XML
+    Raw(CompositeString),
+    /// Parts of a `multipart/form-data` body
+    Multipart(Vec<MultipartPart>),
+    /// File given as `< ./path` whose bytes are sent as they are.
+    /// Files with a JSON content type are read into a `Json` body instead.
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct MultipartPart {
+    /// Headers of the part, like `Content-Disposition: form-data; name="file"`
+    pub headers: Vec<Header>,
+    pub content: PartContent,
+}
+
+#[derive(Debug, Clone)]
+pub enum PartContent {
+    Text(CompositeString),
+    /// File given as `< ./path`, relative to the file containing the request
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct FormField {
+    pub name: CompositeString,
+    pub value: CompositeString,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryParameter {
+    pub name: CompositeString,
+    pub value: Option<CompositeString>,
 }
 
 #[derive(Debug)]
@@ -116,16 +228,27 @@ pub enum Variable {
 #[derive(Debug, Clone)]
 pub enum Generator {
     Guid,
+    /// A random integer between the two bounds, both inclusive
+    Int(i64, i64),
+    /// A random alphanumeric string of the given length
+    String(usize),
+    Email,
+    /// The current UTC time shifted by the offset in seconds, with an optional `strftime` format
+    Now(i64, Option<String>),
+    /// A counter starting at 1 that increases with every use
+    Sequence,
 }
 
 #[derive(Debug)]
 pub struct Snapshot {
     pub status: Status,
     pub headers: Vec<Header>,
-    pub body: Option<Json>,
+    pub body: Option<ResponseBody>,
+    /// File given with `<<<` that holds the expected body
+    pub body_file: Option<PathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Status {
     Value(Number),
     Pattern(String),
@@ -137,16 +260,205 @@ pub enum Comparison {
     Ignore,
     TimestampFormat(CompositeString),
     Guid,
+    Regex(String),
+    Type(ValueType),
+    Numeric(NumericComparison),
+}
+
+#[derive(Debug, Clone)]
+pub enum NumericComparison {
+    Range(Number, Number),
+    Approx(Number, Number),
+    GreaterThan(Number),
+    GreaterOrEqual(Number),
+    LessThan(Number),
+    LessOrEqual(Number),
+}
+
+impl Display for NumericComparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NumericComparison::Range(min, max) => write!(f, "range({min}, {max})"),
+            NumericComparison::Approx(value, tolerance) => {
+                write!(f, "approx({value}, {tolerance})")
+            }
+            NumericComparison::GreaterThan(bound) => write!(f, "gt({bound})"),
+            NumericComparison::GreaterOrEqual(bound) => write!(f, "gte({bound})"),
+            NumericComparison::LessThan(bound) => write!(f, "lt({bound})"),
+            NumericComparison::LessOrEqual(bound) => write!(f, "lte({bound})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ValueType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::Integer => "integer",
+            ValueType::Boolean => "bool",
+            ValueType::Array => "array",
+            ValueType::Object => "object",
+        };
+        write!(f, "{}", str)
+    }
 }
 
 #[derive(Debug)]
 pub struct SnapResponse {
     pub status: u16,
     pub headers: HashMap<String, Header>,
-    pub body: Option<Json>,
+    pub body: Option<ResponseBody>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+/// Body of a response, in the format given by its `Content-Type` header
+#[derive(Debug, Clone)]
+pub enum ResponseBody {
+    Json(Json),
+    /// Text like HTML or XML, which is compared without trailing whitespace
+    Text(String),
+    /// Bodies that are not text are compared by their size and SHA-256 hash
+    Binary {
+        size: usize,
+        sha256: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    /// JSON pointer to the mismatching part, e.g. `/status`, `/headers/date` or `/body/items/0`
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+    pub reason: MismatchReason,
+}
+
+impl Mismatch {
+    pub fn new(path: String, expected: String, actual: String, reason: MismatchReason) -> Self {
+        return Self {
+            path,
+            expected,
+            actual,
+            reason,
+        };
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let or_nothing = |value: &String| {
+            if value.is_empty() {
+                String::from("nothing")
+            } else {
+                value.clone()
+            }
+        };
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(
+            f,
+            "{}, expected {} but got {}",
+            self.reason,
+            or_nothing(&self.expected),
+            or_nothing(&self.actual)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MismatchReason {
+    NotEqual,
+    InvalidFormat,
+    WrongType,
+    Missing,
+    Unexpected,
+    TooFewElements,
+    NotFound,
+    NoSnapshot,
+}
+
+impl Display for MismatchReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            MismatchReason::NotEqual => "value differs",
+            MismatchReason::InvalidFormat => "format differs",
+            MismatchReason::WrongType => "type differs",
+            MismatchReason::Missing => "missing from response",
+            MismatchReason::Unexpected => "not in snapshot",
+            MismatchReason::TooFewElements => "too few elements",
+            MismatchReason::NotFound => "elements not found",
+            MismatchReason::NoSnapshot => "no snapshot",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub requests: Vec<RequestReport>,
+}
+
+impl FileReport {
+    pub fn passed(&self) -> bool {
+        return self
+            .requests
+            .iter()
+            .all(|request| request.matched_option.is_some());
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequestReport {
+    /// Index of the request within the file, counting imported requests and delays
+    pub index: usize,
+    /// Name given after `###` or the markdown heading above the request
+    pub name: Option<String>,
+    pub verb: HttpVerb,
+    /// Url after variables have been replaced
+    pub url: String,
+    /// Headers sent, including default headers from the client options
+    pub headers: BTreeMap<String, String>,
+    pub status: u16,
+    /// Time spent sending the request and comparing the response
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    pub matched_option: Option<usize>,
+    /// Variables stored from the response by the matching snapshot option
+    pub captured_variables: BTreeMap<String, Value>,
+    /// When no snapshot option matched, these are the differences to the closest option
+    pub mismatches: Vec<Mismatch>,
+    /// When no snapshot option matched, the closest option and the response as formatted text
+    #[serde(skip)]
+    pub diff: Option<SnapshotDiff>,
+}
+
+fn serialize_millis<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    return serializer.serialize_u128(duration.as_millis());
+}
+
+#[derive(Debug)]
+pub struct SnapshotDiff {
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum HttpVerb {
     CONNECT,
     DELETE,
@@ -159,6 +471,12 @@ pub enum HttpVerb {
     TRACE,
 }
 
+impl Display for HttpVerb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CompositeString {
     pub parts: Vec<CompositeStringPart>,
@@ -278,7 +596,9 @@ impl Serialize for Value {
         S: Serializer,
     {
         match self {
-            Value::VariableReference(name) => panic!("Variable name {name} is unknown"),
+            Value::VariableReference(name) => Err(serde::ser::Error::custom(
+                HttpSnapError::UnknownVariable(name.clone()),
+            )),
             Value::Object(val) => val.serialize(serializer),
             Value::Array(val) => val.serialize(serializer),
             Value::String(val) => serializer.serialize_str(&val.to_string()),
@@ -298,6 +618,8 @@ impl From<String> for Value {
 #[derive(Debug, Clone)]
 pub struct Object {
     pub members: Vec<Member>,
+    /// Written as `...` after the members, the response may have other members
+    pub partial: bool,
 }
 
 impl Serialize for Object {
@@ -326,18 +648,39 @@ pub enum Array {
     StartsWith(Vec<Element>),
     Contains(Vec<Element>),
     EndsWith(Vec<Element>),
+    /// The same elements in any order, written as `[~ 1, 2 ~]`
+    Unordered(Vec<Element>),
+    /// At least these elements in any order, written as `[~ 1, 2 ... ~]`
+    ContainsAll(Vec<Element>),
 }
 
 impl Array {
-    pub(crate) fn get_known_elements(&self) -> Vec<Element> {
+    pub(crate) fn get_known_elements(&self) -> Result<Vec<Element>, HttpSnapError> {
         return match self {
-            Array::Literal(elements) => elements.clone(),
-            Array::StartsWith(elements) => elements.clone(),
-            Array::Contains(elements) => elements.clone(),
-            Array::EndsWith(elements) => elements.clone(),
-            Array::VariableReference(name) => {
-                panic!("Variable named {name} has not been replaced yet")
-            }
+            Array::Literal(elements) => Ok(elements.clone()),
+            Array::StartsWith(elements) => Ok(elements.clone()),
+            Array::Contains(elements) => Ok(elements.clone()),
+            Array::EndsWith(elements) => Ok(elements.clone()),
+            Array::Unordered(elements) => Ok(elements.clone()),
+            Array::ContainsAll(elements) => Ok(elements.clone()),
+            Array::VariableReference(name) => Err(HttpSnapError::InvalidVariable {
+                name: name.clone(),
+                reason: String::from("has not been replaced yet"),
+            }),
+        };
+    }
+}
+
+impl Array {
+    /// Creates an array of the same kind, for example unordered, with other elements
+    pub(crate) fn with_elements(&self, elements: Vec<Element>) -> Array {
+        return match self {
+            Array::StartsWith(_) => Array::StartsWith(elements),
+            Array::Contains(_) => Array::Contains(elements),
+            Array::EndsWith(_) => Array::EndsWith(elements),
+            Array::Unordered(_) => Array::Unordered(elements),
+            Array::ContainsAll(_) => Array::ContainsAll(elements),
+            Array::Literal(_) | Array::VariableReference(_) => Array::Literal(elements),
         };
     }
 }
@@ -347,8 +690,11 @@ impl Serialize for Array {
     where
         S: Serializer,
     {
-        let mut seq = serializer.serialize_seq(Some(self.get_known_elements().len()))?;
-        for element in &self.get_known_elements() {
+        let elements = self
+            .get_known_elements()
+            .map_err(serde::ser::Error::custom)?;
+        let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+        for element in &elements {
             seq.serialize_element(element)?;
         }
         return seq.end();
@@ -362,6 +708,15 @@ pub enum Number {
     Exponent(String),
 }
 
+impl Number {
+    pub fn as_f64(&self) -> f64 {
+        return match self {
+            Number::Int(val) => *val as f64,
+            Number::Fraction(val) | Number::Exponent(val) => val.parse().unwrap_or(f64::NAN),
+        };
+    }
+}
+
 impl Serialize for Number {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
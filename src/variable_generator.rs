@@ -1,18 +1,114 @@
-﻿use std::collections::HashMap;
-use uuid::Uuid;
-use crate::types::{Generator, Value, Variable};
+﻿use crate::error::HttpSnapError;
+use crate::types::{Generator, Number, Value, Variable};
+use chrono::{SecondsFormat, TimeDelta, Utc};
+use itertools::Itertools;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use uuid::Builder;
 
-pub fn generate_variables(input: HashMap<String, Variable>) -> HashMap<String, Value> {
-    let mut variables = HashMap::new();
-    for (var_name, var_value) in input {
-        match var_value {
-            Variable::Value(value) => variables.insert(var_name, value),
-            Variable::Generator(generator) => match generator {
-                Generator::Guid => {
-                    variables.insert(var_name, Value::from(Uuid::new_v4().to_string()))
+pub struct VariableGenerator {
+    rng: StdRng,
+    sequence: u64,
+}
+
+impl Default for VariableGenerator {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl VariableGenerator {
+    pub fn new() -> Self {
+        return Self {
+            rng: StdRng::from_entropy(),
+            sequence: 0,
+        };
+    }
+
+    /// Creates a generator that always produces the same values for the same seed and label.
+    /// The label gives each file its own values, independent of the other files in the run
+    pub fn with_seed(seed: u64, label: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(seed.to_le_bytes());
+        hasher.update(label.as_bytes());
+        return Self {
+            rng: StdRng::from_seed(hasher.finalize().into()),
+            sequence: 0,
+        };
+    }
+
+    /// Uses the path relative to the working directory as label, so a single file can be
+    /// replayed on its own, and files with the same name in other directories get other values
+    pub fn for_file(seed: Option<u64>, path: &Path) -> Self {
+        let Some(seed) = seed else {
+            return Self::new();
+        };
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        let relative = path.strip_prefix(&current_dir).unwrap_or(path);
+        let label = relative
+            .components()
+            .filter(|component| component != &Component::CurDir)
+            .map(|component| component.as_os_str().to_string_lossy())
+            .join("/");
+        return Self::with_seed(seed, &label);
+    }
+
+    pub fn generate_variables(
+        &mut self,
+        input: HashMap<String, Variable>,
+    ) -> Result<HashMap<String, Value>, HttpSnapError> {
+        let mut variables = HashMap::new();
+        // Sorted so that sequences are handed out in a predictable order
+        for (var_name, var_value) in input.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+            match var_value {
+                Variable::Value(value) => variables.insert(var_name, value),
+                Variable::Generator(generator) => {
+                    let value = self.generate(&generator).map_err(|reason| {
+                        HttpSnapError::InvalidVariable {
+                            name: var_name.clone(),
+                            reason,
+                        }
+                    })?;
+                    variables.insert(var_name, value)
                 }
-            },
+            };
+        }
+        return Ok(variables);
+    }
+
+    fn generate(&mut self, generator: &Generator) -> Result<Value, String> {
+        let value = match generator {
+            Generator::Guid => {
+                let uuid = Builder::from_random_bytes(self.rng.gen()).into_uuid();
+                Value::from(uuid.to_string())
+            }
+            Generator::Int(min, max) => Value::Number(Number::Int(self.rng.gen_range(*min..=*max))),
+            Generator::String(length) => {
+                Value::from(Alphanumeric.sample_string(&mut self.rng, *length))
+            }
+            Generator::Email => {
+                let name = Alphanumeric.sample_string(&mut self.rng, 10).to_lowercase();
+                Value::from(format!("user-{name}@example.com"))
+            }
+            Generator::Now(offset, format) => {
+                let time = TimeDelta::try_seconds(*offset)
+                    .and_then(|offset| Utc::now().checked_add_signed(offset))
+                    .ok_or_else(|| format!("could not be generated, {offset}s is out of range"))?;
+                let formatted = match format {
+                    Some(format) => time.format(format).to_string(),
+                    None => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                };
+                Value::from(formatted)
+            }
+            Generator::Sequence => {
+                self.sequence += 1;
+                Value::Number(Number::Int(self.sequence as i64))
+            }
         };
+        return Ok(value);
     }
-    return variables;
 }
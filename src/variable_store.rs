@@ -1,28 +1,92 @@
-﻿use crate::types::{
-    Array, CompositeString, CompositeStringPart, Element, Header, HttpFile, Json, Member, Object,
-    SnapResponse, Snapshot, Value, Variable,
+﻿use crate::comparer;
+use crate::error::HttpSnapError;
+use crate::types::{
+    Array, Capture, CaptureSource, CompareOptions, Comparison, CompositeString,
+    CompositeStringPart, Element, FormField, Header, HttpFile, Json, Member, Mismatch,
+    MismatchReason, MultipartPart, Object, PartContent, PathSegment, QueryParameter, RequestBody,
+    ResponseBody, SnapResponse, Snapshot, Value, Variable,
 };
-use crate::variable_generator;
-use std::collections::HashMap;
+use crate::variable_generator::VariableGenerator;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
 
 pub(crate) struct VariableStore {
     pub(crate) variables: HashMap<String, Value>,
+    generator: VariableGenerator,
 }
 
 impl VariableStore {
     pub fn new() -> Self {
         return Self {
             variables: HashMap::new(),
+            generator: VariableGenerator::new(),
         };
     }
 
-    pub(crate) fn update_variables(&mut self, snapshot: &Snapshot, response: &SnapResponse) {
-        self.extract_variables_from_headers(&snapshot.headers, &response.headers);
-        if let Some(snapshot_body) = &snapshot.body {
-            if let Some(response_body) = &response.body {
-                self.extract_variables_from_body(&snapshot_body.element, &response_body.element);
+    pub(crate) fn with_generator(generator: VariableGenerator) -> Self {
+        return Self {
+            variables: HashMap::new(),
+            generator,
+        };
+    }
+
+    /// Stores the variables marked in the snapshot and returns the captured ones
+    pub(crate) fn update_variables(
+        &mut self,
+        snapshot: &Snapshot,
+        response: &SnapResponse,
+        options: &CompareOptions,
+    ) -> BTreeMap<String, Value> {
+        let mut captured = VariableStore::new();
+        captured.extract_variables_from_headers(&snapshot.headers, &response.headers);
+        if let (Some(ResponseBody::Json(snapshot_body)), Some(ResponseBody::Json(response_body))) =
+            (&snapshot.body, &response.body)
+        {
+            let (snapshot_element, response_element) =
+                (&snapshot_body.element, &response_body.element);
+            captured.extract_variables_from_body(snapshot_element, response_element, options);
+        }
+        self.variables.extend(captured.variables.clone());
+        return captured.variables.into_iter().collect();
+    }
+
+    /// Evaluates the `CAPTURE` section against a matching response,
+    /// returning the captured variables and a mismatch for each one that is missing
+    pub(crate) fn capture_variables(
+        &mut self,
+        captures: &Vec<Capture>,
+        response: &SnapResponse,
+    ) -> (BTreeMap<String, Value>, Vec<Mismatch>) {
+        let mut captured = BTreeMap::new();
+        let mut missing = Vec::new();
+        for capture in captures {
+            let value = match &capture.source {
+                CaptureSource::Header(name) => response
+                    .headers
+                    .get(name)
+                    .map(|header| Value::String(header.value.clone())),
+                CaptureSource::Body(segments) => match &response.body {
+                    Some(ResponseBody::Json(json)) => find_value(&json.element, segments),
+                    Some(ResponseBody::Text(text)) if segments.is_empty() => {
+                        Some(Value::from(text.clone()))
+                    }
+                    _ => None,
+                },
+            };
+            match value {
+                Some(value) => {
+                    captured.insert(capture.name.clone(), value);
+                }
+                None => missing.push(Mismatch::new(
+                    format!("/captures/{}", capture.name),
+                    capture.source.to_string(),
+                    String::new(),
+                    MismatchReason::Missing,
+                )),
             }
         }
+        self.variables.extend(captured.clone());
+        return (captured, missing);
     }
 
     fn extract_variables_from_headers(
@@ -31,10 +95,14 @@ impl VariableStore {
         response_headers: &HashMap<String, Header>,
     ) {
         for header in snapshot_headers {
-            if let Some(variable_name) = &header.variable_store {
+            // Missing headers are reported by the comparer, so they are skipped here
+            let response_header = response_headers.get(&header.name);
+            if let (Some(variable_name), Some(response_header)) =
+                (&header.variable_store, response_header)
+            {
                 self.variables.insert(
                     variable_name.to_string(),
-                    Value::String(response_headers.get(&header.name).unwrap().value.clone()),
+                    Value::String(capture(&header.comparison, response_header.value.clone())),
                 );
             }
         }
@@ -44,191 +112,361 @@ impl VariableStore {
         &mut self,
         snapshot_element: &Element,
         response_element: &Element,
+        options: &CompareOptions,
     ) {
         if let Some(name) = &snapshot_element.variable_store {
-            self.variables
-                .insert(name.clone(), response_element.value.clone());
+            let value = match &response_element.value {
+                Value::String(string) => {
+                    Value::String(capture(&snapshot_element.comparison, string.clone()))
+                }
+                value => value.clone(),
+            };
+            self.variables.insert(name.clone(), value);
         }
 
         match (&snapshot_element.value, &response_element.value) {
             (Value::Object(snapshot_object), Value::Object(response_object)) => {
-                for (index, member) in snapshot_object.members.iter().enumerate() {
-                    self.extract_variables_from_body(
-                        &member.value,
-                        &response_object.members.get(index).unwrap().value,
-                    )
+                for member in &snapshot_object.members {
+                    let response_member = response_object
+                        .members
+                        .iter()
+                        .find(|response_member| response_member.key == member.key);
+                    if let Some(response_member) = response_member {
+                        self.extract_variables_from_body(
+                            &member.value,
+                            &response_member.value,
+                            options,
+                        )
+                    }
                 }
             }
             (Value::Array(snapshot_array), Value::Array(response_array)) => {
-                for (index, element) in snapshot_array.get_known_elements().iter().enumerate() {
-                    self.extract_variables_from_body(
-                        element,
-                        response_array.get_known_elements().get(index).unwrap(),
-                    )
+                let (Ok(snapshot_elements), Ok(response_elements)) = (
+                    snapshot_array.get_known_elements(),
+                    response_array.get_known_elements(),
+                ) else {
+                    return;
+                };
+                // Unordered elements are paired the same way the comparer matched them
+                let response_indices = match snapshot_array {
+                    Array::Unordered(_) | Array::ContainsAll(_) => {
+                        comparer::assign_elements(&snapshot_elements, &response_elements, options)
+                    }
+                    _ => (0..snapshot_elements.len()).map(Some).collect(),
+                };
+                for (element, response_index) in snapshot_elements.iter().zip(response_indices) {
+                    if let Some(response_element) =
+                        response_index.and_then(|index| response_elements.get(index))
+                    {
+                        self.extract_variables_from_body(element, response_element, options)
+                    }
                 }
             }
             _ => (),
         }
     }
 
-    pub(crate) fn replace_variables(&mut self, input: HttpFile) -> HttpFile {
-        let variables = variable_generator::generate_variables(input.variables);
-        self.extend_variables(&variables);
-        let url_replaced = self.replace_in_composite_string(&input.url);
-        let header_replaced = self.replace_in_headers(&input.headers);
-        let body_replaced = self.replace_in_body(&input.body);
-        let snapshot_replaced = self.replace_in_snapshots(input.snapshots);
-        return HttpFile {
+    pub(crate) fn replace_variables(&mut self, input: HttpFile) -> Result<HttpFile, HttpSnapError> {
+        let variables = self.generator.generate_variables(input.variables)?;
+        self.extend_variables(&variables)?;
+        let url_replaced = self.replace_in_composite_string(&input.url)?;
+        let query_parameters_replaced =
+            self.replace_in_query_parameters(&input.query_parameters)?;
+        let header_replaced = self.replace_in_headers(&input.headers)?;
+        let body_replaced = self.replace_in_request_body(&input.body)?;
+        let snapshot_replaced = self.replace_in_snapshots(input.snapshots)?;
+        return Ok(HttpFile {
             variables: variables
                 .into_iter()
                 .map(|(k, v)| (k, Variable::Value(v)))
                 .collect(),
             verb: input.verb,
             url: url_replaced,
+            query_parameters: query_parameters_replaced,
             headers: header_replaced,
             body: body_replaced,
             snapshots: snapshot_replaced,
-        };
+            captures: input.captures,
+        });
     }
 
-    pub(crate) fn extend_variables(&mut self, new_variables: &HashMap<String, Value>) {
+    pub(crate) fn extend_variables(
+        &mut self,
+        new_variables: &HashMap<String, Value>,
+    ) -> Result<(), HttpSnapError> {
         for (new_var_name, new_var_value) in new_variables {
-            let value = self.replace_in_value(new_var_value);
+            let value = self.replace_in_value(new_var_value)?;
             self.variables.insert(new_var_name.clone(), value);
         }
+        return Ok(());
     }
 
-    fn replace_in_headers(&self, headers: &Vec<Header>) -> Vec<Header> {
+    fn replace_in_headers(&self, headers: &Vec<Header>) -> Result<Vec<Header>, HttpSnapError> {
         let mut result = Vec::new();
         for header in headers {
             result.push(Header {
                 name: header.name.clone(),
                 comparison: header.comparison.clone(),
-                value: self.replace_in_composite_string(&header.value),
+                value: self.replace_in_composite_string(&header.value)?,
                 variable_store: header.variable_store.clone(),
             });
         }
-        return result;
+        return Ok(result);
     }
 
-    fn replace_in_value(&self, value: &Value) -> Value {
-        return match value {
-            Value::VariableReference(name) => self.look_up_variable(&name),
+    fn replace_in_query_parameters(
+        &self,
+        parameters: &Vec<QueryParameter>,
+    ) -> Result<Vec<QueryParameter>, HttpSnapError> {
+        let mut result = Vec::new();
+        for parameter in parameters {
+            let value = match &parameter.value {
+                Some(value) => Some(self.replace_in_composite_string(value)?),
+                None => None,
+            };
+            result.push(QueryParameter {
+                name: self.replace_in_composite_string(&parameter.name)?,
+                value,
+            });
+        }
+        return Ok(result);
+    }
+
+    fn replace_in_value(&self, value: &Value) -> Result<Value, HttpSnapError> {
+        return Ok(match value {
+            Value::VariableReference(name) => self.look_up_variable(&name)?,
             Value::Boolean(_) | Value::Null() | Value::Number(_) => value.clone(),
-            Value::String(val) => Value::String(self.replace_in_composite_string(val)),
-            Value::Array(array) => Value::Array(self.replace_in_array(array)),
-            Value::Object(object) => Value::Object(self.replace_in_object(object)),
-        };
+            Value::String(val) => Value::String(self.replace_in_composite_string(val)?),
+            Value::Array(array) => Value::Array(self.replace_in_array(array)?),
+            Value::Object(object) => Value::Object(self.replace_in_object(object)?),
+        });
     }
 
-    fn look_up_variable(&self, name: &str) -> Value {
-        if self.variables.contains_key(name) {
-            return self.variables.get(name).unwrap().clone();
+    fn look_up_variable(&self, name: &str) -> Result<Value, HttpSnapError> {
+        if let Some(process_variable) = name.strip_prefix("$env.") {
+            return std::env::var(process_variable)
+                .map(Value::from)
+                .map_err(|error| HttpSnapError::InvalidVariable {
+                    name: name.to_string(),
+                    reason: format!("could not be read from the process environment, {error}"),
+                });
         }
+        return match self.variables.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(HttpSnapError::UnknownVariable(name.to_string())),
+        };
+    }
 
-        log::error!("Variable named \"{}\" was not found!", name);
-        panic!("Variable named \"{}\" was not found!", name);
+    fn replace_in_request_body(
+        &self,
+        body: &Option<RequestBody>,
+    ) -> Result<Option<RequestBody>, HttpSnapError> {
+        let replaced = match body {
+            Some(RequestBody::Json(json)) => {
+                let replaced_json = self.replace_in_body(&Some(json.clone()))?;
+                replaced_json.map(RequestBody::Json)
+            }
+            Some(RequestBody::Form(fields)) => {
+                let mut replaced_fields = Vec::new();
+                for field in fields {
+                    replaced_fields.push(FormField {
+                        name: self.replace_in_composite_string(&field.name)?,
+                        value: self.replace_in_composite_string(&field.value)?,
+                    });
+                }
+                Some(RequestBody::Form(replaced_fields))
+            }
+            Some(RequestBody::Raw(text)) => {
+                Some(RequestBody::Raw(self.replace_in_composite_string(text)?))
+            }
+            Some(RequestBody::Multipart(parts)) => {
+                let mut replaced_parts = Vec::new();
+                for part in parts {
+                    let content = match &part.content {
+                        PartContent::Text(text) => {
+                            PartContent::Text(self.replace_in_composite_string(text)?)
+                        }
+                        PartContent::File(path) => PartContent::File(path.clone()),
+                    };
+                    replaced_parts.push(MultipartPart {
+                        headers: self.replace_in_headers(&part.headers)?,
+                        content,
+                    });
+                }
+                Some(RequestBody::Multipart(replaced_parts))
+            }
+            Some(RequestBody::File(path)) => Some(RequestBody::File(path.clone())),
+            None => None,
+        };
+        return Ok(replaced);
     }
 
-    fn replace_in_body(&self, body: &Option<Json>) -> Option<Json> {
+    fn replace_in_body(&self, body: &Option<Json>) -> Result<Option<Json>, HttpSnapError> {
         if let Some(json) = body {
-            return Some(Json {
+            return Ok(Some(Json {
                 element: Element {
-                    value: self.replace_in_value(&json.element.value),
+                    value: self.replace_in_value(&json.element.value)?,
                     variable_store: json.element.variable_store.clone(),
                     comparison: json.element.comparison.clone(),
                 },
-            });
+            }));
         }
 
-        return None;
+        return Ok(None);
     }
 
-    fn replace_in_composite_string(&self, url: &CompositeString) -> CompositeString {
+    fn replace_in_composite_string(
+        &self,
+        url: &CompositeString,
+    ) -> Result<CompositeString, HttpSnapError> {
         let mut replaced_url = Vec::new();
         for part in &url.parts {
             match part {
                 CompositeStringPart::Literal(_) => replaced_url.push(part.clone()),
                 CompositeStringPart::VariableName(name) => {
-                    let value = self.look_up_variable(name);
+                    let value = self.look_up_variable(name)?;
                     let value_as_string = match value {
                         Value::String(val) => val.to_string(),
                         Value::Number(val) => val.to_string(),
                         Value::Boolean(val) => val.to_string(),
-                        _ => panic!("Variable named {name} cannot be used in string"),
+                        _ => {
+                            return Err(HttpSnapError::InvalidVariable {
+                                name: name.clone(),
+                                reason: String::from("cannot be used in string"),
+                            })
+                        }
                     };
                     replaced_url.push(CompositeStringPart::Literal(value_as_string))
                 }
             }
         }
 
-        return CompositeString {
+        return Ok(CompositeString {
             parts: replaced_url,
-        };
+        });
     }
 
-    fn replace_in_array(&self, array: &Array) -> Array {
+    fn replace_in_array(&self, array: &Array) -> Result<Array, HttpSnapError> {
         match &array {
             Array::VariableReference(name) => {
-                let variable_value = self.look_up_variable(&name);
+                let variable_value = self.look_up_variable(&name)?;
                 match variable_value {
-                    Value::Array(value) => value,
-                    _ => panic!("Variable {name} is not of type array"),
+                    Value::Array(value) => Ok(value),
+                    _ => Err(HttpSnapError::InvalidVariable {
+                        name: name.clone(),
+                        reason: String::from("is not of type array"),
+                    }),
                 }
             }
             Array::Literal(elements) => {
-                return Array::Literal(self.replace_in_elements(elements));
+                return Ok(Array::Literal(self.replace_in_elements(elements)?));
             }
             Array::StartsWith(elements) => {
-                return Array::StartsWith(self.replace_in_elements(elements));
+                return Ok(Array::StartsWith(self.replace_in_elements(elements)?));
             }
             Array::Contains(elements) => {
-                return Array::Contains(self.replace_in_elements(elements));
+                return Ok(Array::Contains(self.replace_in_elements(elements)?));
             }
             Array::EndsWith(elements) => {
-                return Array::EndsWith(self.replace_in_elements(elements));
+                return Ok(Array::EndsWith(self.replace_in_elements(elements)?));
+            }
+            Array::Unordered(elements) => {
+                return Ok(Array::Unordered(self.replace_in_elements(elements)?));
+            }
+            Array::ContainsAll(elements) => {
+                return Ok(Array::ContainsAll(self.replace_in_elements(elements)?));
             }
         }
     }
-    
-    fn replace_in_elements(&self, elements: &Vec<Element>) -> Vec<Element> {
+
+    fn replace_in_elements(&self, elements: &Vec<Element>) -> Result<Vec<Element>, HttpSnapError> {
         let mut replaced = Vec::new();
         for element in elements {
             replaced.push(Element {
-                value: self.replace_in_value(&element.value),
+                value: self.replace_in_value(&element.value)?,
                 variable_store: element.variable_store.clone(),
                 comparison: element.comparison.clone(),
             });
         }
-        return replaced;
+        return Ok(replaced);
     }
 
-    fn replace_in_object(&self, object: &Object) -> Object {
+    fn replace_in_object(&self, object: &Object) -> Result<Object, HttpSnapError> {
         let mut replaced = Vec::new();
         for member in &object.members {
             replaced.push(Member {
                 key: member.key.clone(),
                 value: Element {
-                    value: self.replace_in_value(&member.value.value),
+                    value: self.replace_in_value(&member.value.value)?,
                     variable_store: member.value.variable_store.clone(),
                     comparison: member.value.comparison.clone(),
                 },
             });
         }
-        return Object { members: replaced };
+        return Ok(Object {
+            members: replaced,
+            partial: object.partial,
+        });
     }
 
-    fn replace_in_snapshots(&self, snapshots: Vec<Snapshot>) -> Vec<Snapshot> {
+    fn replace_in_snapshots(
+        &self,
+        snapshots: Vec<Snapshot>,
+    ) -> Result<Vec<Snapshot>, HttpSnapError> {
         let mut result = Vec::new();
         for snapshot in snapshots {
-            let headers = self.replace_in_headers(&snapshot.headers);
-            let body = self.replace_in_body(&snapshot.body);
+            let headers = self.replace_in_headers(&snapshot.headers)?;
+            let body = match snapshot.body {
+                Some(ResponseBody::Json(json)) => {
+                    self.replace_in_body(&Some(json))?.map(ResponseBody::Json)
+                }
+                body => body,
+            };
             result.push(Snapshot {
                 status: snapshot.status,
                 headers,
                 body,
+                body_file: snapshot.body_file,
             });
         }
-        return result;
+        return Ok(result);
     }
 }
+
+// A regex comparison stores its first capture group, so `{{id:regex("/orders/(\\d+)")}}`
+// keeps only the id. Without a group, or when nothing matched, the whole value is stored.
+fn capture(comparison: &Option<Comparison>, value: CompositeString) -> CompositeString {
+    let Some(Comparison::Regex(pattern)) = comparison else {
+        return value;
+    };
+    let text = value.to_string();
+    let captured = Regex::new(pattern)
+        .ok()
+        .and_then(|regex| Some(regex.captures(&text)?.get(1)?.as_str().to_string()));
+    return match captured {
+        Some(captured) => CompositeString::from(captured),
+        None => value,
+    };
+}
+
+fn find_value(element: &Element, segments: &[PathSegment]) -> Option<Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Some(element.value.clone());
+    };
+    let next = match (&element.value, segment) {
+        (Value::Object(object), PathSegment::Key(key)) => object
+            .members
+            .iter()
+            .find(|member| &member.key == key)
+            .map(|member| member.value.clone()),
+        (Value::Array(array), PathSegment::Index(index)) => {
+            array.get_known_elements().ok()?.get(*index).cloned()
+        }
+        (Value::Array(array), PathSegment::Key(key)) => {
+            let index = key.parse::<usize>().ok()?;
+            array.get_known_elements().ok()?.get(index).cloned()
+        }
+        _ => None,
+    };
+    return find_value(&next?, rest);
+}
@@ -1,7 +1,10 @@
-﻿use std::collections::HashMap;
-use std::sync::Once;
+﻿use http_snap::run;
+use http_snap::types::{ClientOptions, ExecuteOptions, FileReport, Value};
 use log::LevelFilter;
-use http_snap::types::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use uuid::Uuid;
 
 static INIT: Once = Once::new();
 
@@ -15,8 +18,58 @@ pub fn init_logger() {
 }
 
 pub fn create_environment_variables(server: &wiremock::MockServer) -> HashMap<String, Value> {
-    return HashMap::from([(
-        "test_host".to_string(),
-        Value::from(server.uri()),
-    )]);
-}
\ No newline at end of file
+    return HashMap::from([("test_host".to_string(), Value::from(server.uri()))]);
+}
+
+/// Temporary directory for tests that write files, removed when dropped so that
+/// failing assertions don't leave it behind
+pub struct TempDir {
+    pub path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("http-snap-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        return Self { path };
+    }
+
+    /// Copies a file from `tests/e2e_inputs` to the same relative path in this directory
+    pub fn copy_input(&self, name: &str) -> PathBuf {
+        let path = self.path.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::copy(Path::new("tests/e2e_inputs").join(name), &path).unwrap();
+        return path;
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs a file with the update options and then as a test, returning both reports
+pub async fn update_and_test(
+    path: &PathBuf,
+    environment_variables: &HashMap<String, Value>,
+    update_options: &ExecuteOptions,
+) -> (FileReport, FileReport) {
+    let update = run(
+        path,
+        environment_variables,
+        update_options,
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    let test = run(
+        path,
+        environment_variables,
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    return (update, test);
+}
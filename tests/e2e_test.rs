@@ -1,9 +1,22 @@
-﻿use http_snap::run;
-use http_snap::types::{ClientOptions, DefaultHeader, Detector, ExecuteOptions, UpdateMode};
+﻿use http_snap::diff::render_unified_diff;
+use http_snap::environment::load_named_environment;
+use http_snap::error::HttpSnapError;
+use http_snap::recorder::{record, RecordOptions};
+use http_snap::report::{create_json_report, create_junit_report};
+use http_snap::run;
+use http_snap::server::{load_routes, serve};
+use http_snap::types::{
+    ClientOptions, DefaultHeader, Detector, ExecuteOptions, MismatchReason, UpdateMode, Value,
+};
 use serde_json::json;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 use uuid::Uuid;
-use wiremock::matchers::{method, path, path_regex};
+use wiremock::matchers::{
+    body_json, body_string, body_string_contains, header_regex, method, path, path_regex,
+    query_param,
+};
 use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
 mod common;
@@ -29,7 +42,7 @@ async fn send_get_with_no_body() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
@@ -53,7 +66,7 @@ async fn post_with_no_response() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
@@ -92,7 +105,7 @@ async fn post_with_body_but_default_header() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
     let request_headers = server
         .received_requests()
         .await
@@ -141,7 +154,7 @@ async fn compare_timestamp_formats() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
@@ -170,7 +183,7 @@ async fn detect_timestamp_formats() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
@@ -199,7 +212,7 @@ async fn detect_timestamp_formats_keeping_values() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
@@ -230,27 +243,30 @@ async fn compare_guid_formats() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
-async fn generate_guid() {
+async fn regex_comparisons() {
     common::init_logger();
     let server = MockServer::start().await;
     Mock::given(method("POST"))
-        .and(path_regex(r"^/ids/.+$"))
-        .respond_with(|req: &Request| {
-            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
-            ResponseTemplate::new(200).set_body_json(json!({
-                "id": id,
-                "name": "Echo"
-            }))
-        })
+        .and(path("/orders"))
+        .respond_with(
+            ResponseTemplate::new(201)
+                .insert_header("location", "/orders/123456")
+                .set_body_json(json!({ "id": "ord-123456" })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/orders/123456"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "ord-123456" })))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/generate_guid.http");
+    path.push("tests/e2e_inputs/regex_comparisons.http");
     let result = run(
         &path,
         &common::create_environment_variables(&server),
@@ -260,254 +276,392 @@ async fn generate_guid() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), true);
 }
 
 #[tokio::test]
-async fn use_generated_variable_in_header() {
+async fn type_comparisons() {
     common::init_logger();
     let server = MockServer::start().await;
-    Mock::given(method("POST"))
-        .and(path_regex(r"^/item/.+$"))
-        .respond_with(|req: &Request| {
-            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
-            ResponseTemplate::new(200)
-                .insert_header("location", format!("/item/{id}"))
-                .set_body_json(json!({
-                    "id": id,
-                    "name": "Echo"
-                }))
-        })
+    Mock::given(method("GET"))
+        .and(path("/orders/summary"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "total": 17,
+            "average": 12.5,
+            "hash": "5d41402abc4b2a76",
+            "open": false,
+            "items": [1, 2],
+            "customer": { "name": "Alice" }
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/orders/count/17"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "count": 1.5 })))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/use_generated_variable_in_header.http");
+    path.push("tests/e2e_inputs/type_comparisons.http");
     let result = run(
         &path,
         &common::create_environment_variables(&server),
-        &ExecuteOptions::new_update(
-            true,
-            UpdateMode::Overwrite,
-            &[Detector::Guid, Detector::Timestamp],
-        ),
+        &ExecuteOptions::new_test(),
         &ClientOptions::default(),
     )
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.requests[0].mismatches.len(), 0);
+    let mismatches = &result.requests[1].mismatches;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].path, "/body/count");
+    assert_eq!(mismatches[0].reason, MismatchReason::WrongType);
 }
 
 #[tokio::test]
-async fn detect_guid_and_timestamp() {
+async fn numeric_comparisons() {
     common::init_logger();
     let server = MockServer::start().await;
+    let body = r#"{"discount": 0.30000000000000004, "price": 19.990000000000002, "quantity": 3,
+        "rating": 4.2, "stock": 7, "total": 1.0}"#;
     Mock::given(method("GET"))
-        .and(path_regex("/guid"))
-        .respond_with(
-            ResponseTemplate::new(200)
-                .insert_header("correlation-id", Uuid::new_v4().to_string().as_str())
-                .set_body_json(json!({
-                    "id": Uuid::new_v4().to_string()
-                })),
-        )
+        .and(path("/pricing"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/detect_guid_and_timestamp.http");
-    let result = run(
+    path.push("tests/e2e_inputs/numeric_comparisons.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let exact = run(
         &path,
-        &common::create_environment_variables(&server),
-        &ExecuteOptions::new_update(
-            true,
-            UpdateMode::Overwrite,
-            &[Detector::Guid, Detector::Timestamp],
-        ),
+        &environment_variables,
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    let mut numeric_options = ExecuteOptions::new_test();
+    numeric_options.compare_options.numeric_equality = true;
+    let numeric = run(
+        &path,
+        &environment_variables,
+        &numeric_options,
         &ClientOptions::default(),
     )
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    let mismatch_paths = exact.requests[0]
+        .mismatches
+        .iter()
+        .map(|mismatch| mismatch.path.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(mismatch_paths, vec!["/body/discount", "/body/total"]);
+    assert_eq!(numeric.passed(), true);
 }
 
 #[tokio::test]
-async fn import_and_use_variable() {
+async fn unordered_arrays() {
     common::init_logger();
     let server = MockServer::start().await;
     Mock::given(method("GET"))
-        .and(path("/tokens"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"token": "12345"})))
-        .mount(&server)
-        .await;
-    Mock::given(method("GET"))
-        .and(path("/token/12345"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"found": true})))
+        .and(path("/users/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "roles": ["reader", "admin", "writer"],
+            "tags": [{ "name": "c" }, { "name": "a" }, { "name": "b" }],
+            "version": 2
+        })))
         .mount(&server)
         .await;
 
-    let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/importer.http");
-    let result = run(
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("unordered_arrays.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let before_update = run(
         &path,
-        &common::create_environment_variables(&server),
+        &environment_variables,
         &ExecuteOptions::new_test(),
         &ClientOptions::default(),
     )
     .await
     .unwrap();
+    let (_, after_update) = common::update_and_test(
+        &path,
+        &environment_variables,
+        &ExecuteOptions::new_update(true, UpdateMode::Overwrite, &[]),
+    )
+    .await;
 
-    assert_eq!(result, true);
+    let content = std::fs::read_to_string(&path).unwrap();
+    let mismatches = &before_update.requests[0].mismatches;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].path, "/body/version");
+    assert_eq!(after_update.passed(), true);
+    assert!(content.contains("\"roles\": [~\n    \"admin\"\n    ...\n  ~]"));
+    assert!(content.contains("\"tags\": [~\n"));
+    assert!(content.contains("\"version\": 2"));
 }
 
 #[tokio::test]
-async fn writing_snapshot() {
+async fn update_changed_unordered_arrays() {
     common::init_logger();
     let server = MockServer::start().await;
+    // The update sees the elements in another order than the test after it
     Mock::given(method("GET"))
-        .and(path("/complex"))
+        .and(path("/users/1"))
         .respond_with(ResponseTemplate::new(200).set_body_json(json!({
-          "name": "Test Data",
-          "id": 123,
-          "active": true,
-          "nestedObject": {
-            "key1": "value1",
-            "key2": {
-              "subKey": 42,
-              "subArray": [
-                1,
-                2,
-                3,
-                {
-                  "deep": "value"
-                }
-              ]
-            },
-            "emptyObject": {}
-          },
-          "arrayOfObjects": [
-            {
-              "a": 1.0,
-              "b": 2
-            },
-            {
-              "a": 3,
-              "b": 4
-            }
-          ],
-          "emptyArray": [],
-          "mixedArray": [
-            null,
-            "string",
-            123,
-            false,
-            {}
-          ],
-          "deeplyNested": {
-            "level1": {
-              "level2": {
-                "level3": {
-                  "level4": "end"
-                }
-              }
-            }
-          },
-          "specialChars": "Quotes \" and backslash \\ and newline \n end",
-          "unicode": "こんにちは世界"
+            "roles": ["writer", "reader"],
+            "tags": [{ "name": "d" }, { "name": "a" }],
+            "version": 1
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/users/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "roles": ["reader", "writer"],
+            "tags": [{ "name": "a" }, { "name": "d" }],
+            "version": 1
         })))
         .mount(&server)
         .await;
 
-    let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/writing_snapshot.http");
-    let result = run(
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("unordered_arrays.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let (_, after_update) = common::update_and_test(
+        &path,
+        &environment_variables,
+        &ExecuteOptions::new_update(true, UpdateMode::Overwrite, &[]),
+    )
+    .await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(after_update.passed(), true);
+    assert!(content.contains("\"roles\": [~\n    \"writer\",\n    \"reader\"\n    ...\n  ~]"));
+    assert!(content.contains("\"tags\": [~\n"));
+}
+
+#[tokio::test]
+async fn update_empty_unordered_array() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/users/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "roles": [],
+            "version": 2
+        })))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("empty_unordered_array.http");
+    let (_, after_update) = common::update_and_test(
         &path,
         &common::create_environment_variables(&server),
-        &ExecuteOptions::new_update(true, UpdateMode::Overwrite, &[Detector::Timestamp]),
-        &ClientOptions::default(),
+        &ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[]),
     )
-    .await
-    .unwrap();
+    .await;
 
-    assert_eq!(result, true);
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(after_update.passed(), true);
+    assert!(content.contains("\"roles\": [~ ~]"));
+    assert!(content.contains("\"version\": 2"));
 }
 
 #[tokio::test]
-async fn markdown_with_single_section() {
+async fn unordered_array_captures() {
     common::init_logger();
     let server = MockServer::start().await;
     Mock::given(method("GET"))
-        .and(path("/no-body"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"hello": "world"})))
+        .and(path("/users/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "tags": [{ "id": 1, "name": "a" }, { "id": 2, "name": "b" }]
+        })))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/simple_markdown.md");
+    path.push("tests/e2e_inputs/unordered_captures.http");
     let result = run(
         &path,
         &common::create_environment_variables(&server),
-        &ExecuteOptions::new_update(
-            true,
-            UpdateMode::Overwrite,
-            &[Detector::Timestamp, Detector::Timestamp],
-        ),
+        &ExecuteOptions::new_test(),
         &ClientOptions::default(),
     )
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    let captured = serde_json::to_value(&result.requests[0].captured_variables).unwrap();
+    assert_eq!(result.passed(), true);
+    assert_eq!(captured, json!({ "a_id": 1, "b_id": 2 }));
 }
 
 #[tokio::test]
-async fn markdown_with_multiple_sections() {
+async fn partial_objects() {
     common::init_logger();
     let server = MockServer::start().await;
     Mock::given(method("GET"))
-        .and(path_regex(r"^/items/[0-9]+$"))
-        .respond_with(|req: &Request| {
-            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
-            ResponseTemplate::new(200).set_body_json(json!({
-                "id": id,
-                "name": format!("Echo {}", id)
-            }))
-        })
+        .and(path("/users/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "address": { "city": "Oslo", "zip": "0150" },
+            "email": "alice@example.com",
+            "id": 1,
+            "meta": { "version": 3 },
+            "name": "Alice"
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/users/2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "email": "bob@example.com",
+            "id": 2,
+            "name": "Bob"
+        })))
         .mount(&server)
         .await;
 
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("partial_objects.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let (before_update, after_update) = common::update_and_test(
+        &path,
+        &environment_variables,
+        &ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[]),
+    )
+    .await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(before_update.requests[0].mismatches.len(), 0);
+    let mismatches = &before_update.requests[1].mismatches;
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].path, "/body/name");
+    assert_eq!(after_update.passed(), true);
+    assert!(content.contains("\"name\": \"Bob\",\n  ...\n}"));
+}
+
+#[tokio::test]
+async fn reject_partial_request_body() {
+    common::init_logger();
+    let server = MockServer::start().await;
+
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/markdown_with_multiple_sections.md");
-    let result = run(
+    path.push("tests/e2e_inputs/partial_request_body.http");
+    let error = run(
         &path,
         &common::create_environment_variables(&server),
-        &ExecuteOptions::new_update(
-            true,
-            UpdateMode::Overwrite,
-            &[Detector::Timestamp, Detector::Timestamp],
-        ),
+        &ExecuteOptions::new_test(),
         &ClientOptions::default(),
     )
     .await
-    .unwrap();
+    .unwrap_err();
 
-    assert_eq!(result, true);
+    let HttpSnapError::Parse(parse_error) = error else {
+        panic!("Expected a parse error but got {error}");
+    };
+    let diagnostic = parse_error.diagnostics.first().unwrap();
+    assert_eq!(diagnostic.line, 4);
+    assert_eq!(diagnostic.message, "`...` is only allowed in snapshots");
 }
 
 #[tokio::test]
-async fn status_pattern() {
+async fn capture_variables() {
     common::init_logger();
     let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .respond_with(
+            ResponseTemplate::new(201)
+                .insert_header("etag", "\"v1\"")
+                .set_body_json(json!({
+                    "meta": { "rate/limit": 5 },
+                    "items": [{ "id": "ord-1" }, { "id": "ord-2" }]
+                })),
+        )
+        .mount(&server)
+        .await;
     Mock::given(method("GET"))
-        .and(path("/good_status"))
-        .respond_with(ResponseTemplate::new(201))
+        .and(path("/orders/ord-1"))
+        .and(query_param("second", "ord-2"))
+        .and(query_param("limit", "5"))
+        .and(header_regex("if-none-match", "^\"v1\"$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": "shipped" })))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("capture_variables.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let (before_update, after_update) = common::update_and_test(
+        &path,
+        &environment_variables,
+        &ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[]),
+    )
+    .await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let captured = &before_update.requests[0].captured_variables;
+    assert_eq!(
+        captured.keys().collect::<Vec<_>>(),
+        vec!["etag", "limit", "order_id", "second_id"]
+    );
+    assert_eq!(before_update.requests[1].mismatches[0].path, "/body/status");
+    assert_eq!(after_update.passed(), true);
+    assert!(content.ends_with("\"status\": \"shipped\"\n}\n\nCAPTURE\n@status = $.status"));
+}
+
+#[tokio::test]
+async fn update_named_captures() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .respond_with(
+            ResponseTemplate::new(201)
+                .insert_header("location", "/orders/7")
+                .set_body_json(json!({ "id": "ord-7", "status": "created", "total": 12 })),
+        )
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("named_captures.http");
+    let (_, after_update) = common::update_and_test(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[]),
+    )
+    .await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(after_update.passed(), true);
+    assert!(content.contains("location: {{order_id:regex(\"^/orders/([0-9]+)$\"):\"/orders/1\"}}"));
+    assert!(content.contains("\"id\": {{id:_}}"));
+    assert!(content.contains("\"total\": {{total:number:10}}"));
+    let captured = &after_update.requests[0].captured_variables;
+    assert_eq!(
+        captured.keys().collect::<Vec<_>>(),
+        vec!["id", "order_id", "total"]
+    );
+}
+
+#[tokio::test]
+async fn missing_capture() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "items": [] })))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/status_pattern.http");
+    path.push("tests/e2e_inputs/missing_capture.http");
     let result = run(
         &path,
         &common::create_environment_variables(&server),
@@ -517,25 +671,27 @@ async fn status_pattern() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), false);
+    assert_eq!(result.requests.len(), 1);
+    assert_eq!(result.requests[0].mismatches[0].path, "/captures/order_id");
+    assert_eq!(
+        result.requests[0].mismatches[0].expected,
+        "$[\"items\"][0][\"id\"]"
+    );
 }
 
 #[tokio::test]
-async fn array_structure_patterns() {
+async fn missing_captured_header() {
     common::init_logger();
     let server = MockServer::start().await;
-    Mock::given(method("GET"))
-        .and(path("/items"))
-        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
-            { "id": 1, "name": "Alice" },
-            { "id": 2, "name": "Bob" },
-            { "id": 3, "name": "Charlie"}
-        ])))
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .respond_with(ResponseTemplate::new(201))
         .mount(&server)
         .await;
 
     let mut path = PathBuf::new();
-    path.push("tests/e2e_inputs/array_structure_patterns.http");
+    path.push("tests/e2e_inputs/missing_captured_header.http");
     let result = run(
         &path,
         &common::create_environment_variables(&server),
@@ -545,5 +701,1310 @@ async fn array_structure_patterns() {
     .await
     .unwrap();
 
-    assert_eq!(result, true);
+    assert_eq!(result.passed(), false);
+    let mismatch = &result.requests[0].mismatches[0];
+    assert_eq!(mismatch.path, "/headers/location");
+    assert!(matches!(mismatch.reason, MismatchReason::Missing));
+}
+
+#[tokio::test]
+async fn generators() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/users"))
+        .respond_with(|req: &Request| {
+            ResponseTemplate::new(200).set_body_raw(req.body.clone(), "application/json")
+        })
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/generators.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let tomorrow = chrono::Utc::now() + chrono::TimeDelta::days(1);
+    assert_eq!(result.passed(), true);
+    assert_eq!(body["due"], tomorrow.format("%Y-%m-%d").to_string());
+
+    path.set_file_name("generator_out_of_range.http");
+    let out_of_range = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+    assert!(matches!(
+        out_of_range,
+        Err(HttpSnapError::InvalidVariable { name, .. }) if name == "expires"
+    ));
+}
+
+#[tokio::test]
+async fn seeded_generators() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/ids/.+$"))
+        .respond_with(|req: &Request| {
+            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
+            ResponseTemplate::new(200).set_body_json(json!({
+                "id": id,
+                "name": "Echo"
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/generate_guid.http");
+    for seed in [7, 7, 8] {
+        let execute_options = ExecuteOptions {
+            seed: Some(seed),
+            ..ExecuteOptions::new_test()
+        };
+        let result = run(
+            &path,
+            &common::create_environment_variables(&server),
+            &execute_options,
+            &ClientOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.passed(), true);
+    }
+
+    // Files with the same name in another directory get other values
+    let directory = common::TempDir::new();
+    let copy = directory.copy_input("generate_guid.http");
+    let execute_options = ExecuteOptions {
+        seed: Some(7),
+        ..ExecuteOptions::new_test()
+    };
+    let copy_result = run(
+        &copy,
+        &common::create_environment_variables(&server),
+        &execute_options,
+        &ClientOptions::default(),
+    )
+    .await;
+
+    let requests = server.received_requests().await.unwrap();
+    let paths = requests.iter().map(|r| r.url.path()).collect::<Vec<_>>();
+    assert_eq!(copy_result.unwrap().passed(), true);
+    assert_eq!(paths[0], paths[1]);
+    assert_ne!(paths[1], paths[2]);
+    assert_ne!(paths[0], paths[3]);
+}
+
+#[tokio::test]
+async fn process_environment_variables() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/profile"))
+        .and(header_regex("authorization", "^Bearer s3cret$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "token": "s3cret" })))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/process_environment.http");
+    std::env::set_var("HTTP_SNAP_TEST_TOKEN", "s3cret");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    std::env::remove_var("HTTP_SNAP_TEST_TOKEN");
+    let missing = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+
+    assert_eq!(result.passed(), true);
+    assert!(matches!(
+        missing,
+        Err(HttpSnapError::InvalidVariable { name, .. }) if name == "$env.HTTP_SNAP_TEST_TOKEN"
+    ));
+}
+
+#[test]
+fn named_environments() {
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("environments/http-snap.env.json");
+    let private_path = path.with_file_name("http-snap.env.private.json");
+    let private = json!({ "$shared": { "page_size": 10 }, "staging": { "token": "secret" } });
+    std::fs::write(&private_path, private.to_string()).unwrap();
+
+    let staging = load_named_environment(&path, "staging").unwrap();
+    let production = load_named_environment(&path, "production").unwrap();
+    let unknown = load_named_environment(&path, "qa");
+
+    let to_json = |variables: &HashMap<String, Value>| serde_json::to_value(variables).unwrap();
+    assert_eq!(
+        to_json(&staging),
+        json!({
+            "api_version": "v2",
+            "page_size": 10,
+            "api_host": "https://staging.example.com",
+            "token": "secret"
+        })
+    );
+    assert_eq!(to_json(&production)["page_size"], 50);
+    let Err(HttpSnapError::InvalidEnvironment { reason, .. }) = unknown else {
+        panic!("Expected an invalid environment error");
+    };
+    assert!(reason.ends_with("found: production, staging"));
+}
+
+#[tokio::test]
+async fn generate_guid() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/ids/.+$"))
+        .respond_with(|req: &Request| {
+            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
+            ResponseTemplate::new(200).set_body_json(json!({
+                "id": id,
+                "name": "Echo"
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/generate_guid.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn use_generated_variable_in_header() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/item/.+$"))
+        .respond_with(|req: &Request| {
+            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
+            ResponseTemplate::new(200)
+                .insert_header("location", format!("/item/{id}"))
+                .set_body_json(json!({
+                    "id": id,
+                    "name": "Echo"
+                }))
+        })
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/use_generated_variable_in_header.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(
+            true,
+            UpdateMode::Overwrite,
+            &[Detector::Guid, Detector::Timestamp],
+        ),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn detect_guid_and_timestamp() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex("/guid"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("correlation-id", Uuid::new_v4().to_string().as_str())
+                .set_body_json(json!({
+                    "id": Uuid::new_v4().to_string()
+                })),
+        )
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/detect_guid_and_timestamp.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(
+            true,
+            UpdateMode::Overwrite,
+            &[Detector::Guid, Detector::Timestamp],
+        ),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn import_and_use_variable() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/tokens"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"token": "12345"})))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/token/12345"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"found": true})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/importer.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn writing_snapshot() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/complex"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+          "name": "Test Data",
+          "id": 123,
+          "active": true,
+          "nestedObject": {
+            "key1": "value1",
+            "key2": {
+              "subKey": 42,
+              "subArray": [
+                1,
+                2,
+                3,
+                {
+                  "deep": "value"
+                }
+              ]
+            },
+            "emptyObject": {}
+          },
+          "arrayOfObjects": [
+            {
+              "a": 1.0,
+              "b": 2
+            },
+            {
+              "a": 3,
+              "b": 4
+            }
+          ],
+          "emptyArray": [],
+          "mixedArray": [
+            null,
+            "string",
+            123,
+            false,
+            {}
+          ],
+          "deeplyNested": {
+            "level1": {
+              "level2": {
+                "level3": {
+                  "level4": "end"
+                }
+              }
+            }
+          },
+          "specialChars": "Quotes \" and backslash \\ and newline \n end",
+          "unicode": "こんにちは世界"
+        })))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/writing_snapshot.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(true, UpdateMode::Overwrite, &[Detector::Timestamp]),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn markdown_with_single_section() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/no-body"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"hello": "world"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/simple_markdown.md");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(
+            true,
+            UpdateMode::Overwrite,
+            &[Detector::Timestamp, Detector::Timestamp],
+        ),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn markdown_with_multiple_sections() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/items/[0-9]+$"))
+        .respond_with(|req: &Request| {
+            let id = req.url.path().rsplit('/').next().unwrap_or("unknown");
+            ResponseTemplate::new(200).set_body_json(json!({
+                "id": id,
+                "name": format!("Echo {}", id)
+            }))
+        })
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/markdown_with_multiple_sections.md");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_update(
+            true,
+            UpdateMode::Overwrite,
+            &[Detector::Timestamp, Detector::Timestamp],
+        ),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn status_pattern() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/good_status"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/status_pattern.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn query_parameters() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/products"))
+        .and(query_param("search", "red shoes"))
+        .and(query_param("page", "2"))
+        .and(query_param("sort", "name"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/query_parameters.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+    assert!(result.requests[0]
+        .url
+        .ends_with("/products?search=red+shoes&page=2&sort=name"));
+}
+
+#[tokio::test]
+async fn non_json_request_bodies() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/login"))
+        .and(body_string("username=alice&password=s3cr&t=1"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .and(body_string(
+            "<order>\n  <customer>alice</customer>\n</order>",
+        ))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/non_json_request_bodies.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn text_request_without_body() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("DELETE"))
+        .and(path("/notes/1"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/text_request_without_body.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body = String::from_utf8_lossy(&requests[0].body);
+    assert_eq!(result.passed(), true);
+    assert!(!body.contains("SNAPSHOT"));
+}
+
+#[tokio::test]
+async fn multipart_upload() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/upload"))
+        .and(header_regex(
+            "content-type",
+            "^multipart/form-data; boundary=",
+        ))
+        .and(body_string_contains(
+            "name=\"title\"\r\n\r\nQuarterly numbers\r\n",
+        ))
+        .and(body_string_contains("filename=\"numbers.txt\""))
+        .and(body_string_contains("first line of the upload"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/multipart_upload.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn body_from_file() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/orders"))
+        .and(body_json(json!({"customer": "alice", "items": [1, 2, 3]})))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/amounts"))
+        .and(body_string("id;amount\n1;20\n"))
+        .respond_with(ResponseTemplate::new(201))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/body_from_file.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn non_json_responses() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing"))
+        .respond_with(ResponseTemplate::new(404).set_body_raw("<h1>Not found</h1>\n", "text/html"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/logo.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0x89, 0x50, 0xff], "image/png"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/count"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("42", "text/plain"))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("non_json_responses.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let update_options =
+        ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[Detector::Timestamp]);
+    let (_, result) = common::update_and_test(&path, &environment_variables, &update_options).await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(result.passed(), true);
+    assert!(content.contains("\n<h1>Not found</h1>\n"));
+    assert!(content.contains(
+        "binary: 3 bytes, sha256: c36ee640e0a885a73726d67791afbe0632614780b1293f51972675aba3b4fe85"
+    ));
+    assert!(content.contains("\n42"));
+}
+
+#[tokio::test]
+async fn ambiguous_text_responses() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/json-like"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{\"id\": 1}\n", "text/plain"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/separators"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("first\n|| second\n\nCAPTURE third\n", "text/plain"),
+        )
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("ambiguous_text_responses.http");
+    let environment_variables = common::create_environment_variables(&server);
+    let update_options =
+        ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[Detector::Timestamp]);
+    let (_, result) = common::update_and_test(&path, &environment_variables, &update_options).await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(result.passed(), true);
+    assert!(content.contains("\ntext: |\n  {\"id\": 1}\n"));
+    assert!(content.ends_with("\ntext: |\n  first\n  || second\n\n  CAPTURE third"));
+}
+
+#[tokio::test]
+async fn external_snapshot_files() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/orders"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "orders": [1, 2] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(["a", "b", "c", "d"])))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("external_snapshot_files.http");
+    directory.copy_input("snapshots/orders.json");
+    let environment_variables = common::create_environment_variables(&server);
+    // The timestamp detector keeps the date header from failing when the second changes
+    let mut update_options =
+        ExecuteOptions::new_update(false, UpdateMode::Overwrite, &[Detector::Timestamp]);
+    update_options
+        .update_options
+        .as_mut()
+        .unwrap()
+        .body_file_threshold = Some(5);
+    let (_, result) = common::update_and_test(&path, &environment_variables, &update_options).await;
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let snapshots = directory.path.join("snapshots");
+    let orders = std::fs::read_to_string(snapshots.join("orders.json")).unwrap();
+    let items = std::fs::read_to_string(snapshots.join("external_snapshot_files_1.json"));
+    assert_eq!(result.passed(), true);
+    assert!(content.contains("<<< snapshots/orders.json"));
+    assert!(content.contains("<<< snapshots/external_snapshot_files_1.json"));
+    assert_eq!(orders, "{\n  \"orders\": [\n    1,\n    2\n  ]\n}\n");
+    assert!(items.unwrap().contains("\"d\""));
+}
+
+#[tokio::test]
+async fn append_to_external_snapshot_files() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/orders"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "orders": [1, 2] })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!(["a", "b", "c", "d"])))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let path = directory.copy_input("external_snapshot_files.http");
+    directory.copy_input("snapshots/orders.json");
+    let mut update_options =
+        ExecuteOptions::new_update(false, UpdateMode::Append, &[Detector::Timestamp]);
+    update_options
+        .update_options
+        .as_mut()
+        .unwrap()
+        .body_file_threshold = Some(5);
+    run(
+        &path,
+        &common::create_environment_variables(&server),
+        &update_options,
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    let snapshots = directory.path.join("snapshots");
+    let orders = std::fs::read_to_string(snapshots.join("orders.json")).unwrap();
+    let files = std::fs::read_dir(&snapshots).unwrap().count();
+    // The existing reference is reused instead of adding a file next to it
+    assert_eq!(content.matches("<<< snapshots/orders.json").count(), 2);
+    assert_eq!(orders, "{\n  \"orders\": [\n    1,\n    2\n  ]\n}\n");
+    assert_eq!(files, 2);
+}
+
+#[tokio::test]
+async fn array_structure_patterns() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/items"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "id": 1, "name": "Alice" },
+            { "id": 2, "name": "Bob" },
+            { "id": 3, "name": "Charlie"}
+        ])))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/array_structure_patterns.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), true);
+}
+
+#[tokio::test]
+async fn report_parse_error() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/no-body"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"hello": "world"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/report_parse_error.http");
+    let error = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap_err();
+
+    let HttpSnapError::Parse(parse_error) = error else {
+        panic!("Expected a parse error but got {error}");
+    };
+    let diagnostic = parse_error.diagnostics.first().unwrap();
+    assert_eq!(diagnostic.path, path);
+    assert_eq!(diagnostic.line, 20);
+    assert_eq!(diagnostic.column, 19);
+    assert_eq!(diagnostic.snippet, "    \"name\": \"Cat\",,");
+}
+
+#[tokio::test]
+async fn report_missing_files() {
+    let mut messages = Vec::new();
+    for path in ["tests/e2e_inputs/none_*.http", "tests/e2e_inputs/typo.htp"] {
+        let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_http-snap"))
+            .args(["test", "--path", path])
+            .output()
+            .await
+            .unwrap();
+        assert_eq!(output.status.success(), false);
+        messages.push(String::from_utf8(output.stderr).unwrap());
+    }
+
+    assert!(messages[0].contains("no .http or .md files match"));
+    assert!(messages[1].contains("Unsupported file type tests/e2e_inputs/typo.htp"));
+}
+
+#[tokio::test]
+async fn report_parse_error_with_imports() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/no-body"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"hello": "world"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/import_parse_error.http");
+    let after_import = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+    path.set_file_name("import_broken_file.http");
+    let in_import = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+
+    let Err(HttpSnapError::Parse(after_import)) = after_import else {
+        panic!("Expected a parse error after the import");
+    };
+    let diagnostic = after_import.diagnostics.first().unwrap();
+    assert_eq!(
+        diagnostic.path,
+        PathBuf::from("tests/e2e_inputs/import_parse_error.http")
+    );
+    assert_eq!(diagnostic.line, 6);
+    assert_eq!(diagnostic.column, 17);
+    let Err(HttpSnapError::Parse(in_import)) = in_import else {
+        panic!("Expected a parse error in the imported file");
+    };
+    let diagnostic = in_import.diagnostics.first().unwrap();
+    assert_eq!(
+        diagnostic.path,
+        PathBuf::from("tests/e2e_inputs/report_parse_error.http")
+    );
+    assert_eq!(diagnostic.line, 20);
+    assert_eq!(diagnostic.column, 19);
+}
+
+#[tokio::test]
+async fn report_parse_error_in_markdown() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/no-body"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"hello": "world"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/markdown_parse_error.md");
+    let error = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap_err();
+
+    let HttpSnapError::Parse(parse_error) = error else {
+        panic!("Expected a parse error but got {error}");
+    };
+    let diagnostic = parse_error.diagnostics.first().unwrap();
+    assert_eq!(diagnostic.path, path);
+    assert_eq!(diagnostic.line, 27);
+    assert_eq!(diagnostic.column, 3);
+    assert_eq!(diagnostic.snippet, "  FETCH {{test_host}}/no-body");
+}
+
+#[tokio::test]
+async fn report_malformed_snapshot_body() {
+    common::init_logger();
+    let server = MockServer::start().await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/malformed_snapshot_body.http");
+    let error = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap_err();
+
+    let HttpSnapError::Parse(parse_error) = error else {
+        panic!("Expected a parse error but got {error}");
+    };
+    let diagnostic = parse_error.diagnostics.first().unwrap();
+    assert_eq!(diagnostic.line, 10);
+    assert_eq!(diagnostic.column, 1);
+}
+
+#[tokio::test]
+async fn report_unknown_variable() {
+    common::init_logger();
+    let server = MockServer::start().await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/report_unknown_variable.http");
+    let error = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap_err();
+
+    assert!(matches!(error, HttpSnapError::UnknownVariable(name) if name == "item_id"));
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn report_all_mismatches() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/person"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "name": "Bob",
+            "age": 30,
+            "tags": ["a", "c"],
+            "extra": true
+        })))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/report_all_mismatches.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), false);
+    let request = result.requests.first().unwrap();
+    let paths = request
+        .mismatches
+        .iter()
+        .map(|mismatch| mismatch.path.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        [
+            "/status",
+            "/headers/content-length",
+            "/body/name",
+            "/body/tags/1",
+            "/body/extra"
+        ]
+    );
+    assert_eq!(request.mismatches[2].expected, "\"Alice\"");
+    assert_eq!(request.mismatches[2].actual, "\"Bob\"");
+    assert_eq!(request.mismatches[4].reason, MismatchReason::Unexpected);
+}
+
+#[tokio::test]
+async fn show_snapshot_diff() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/person"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "id": "0f8fad5b-d9cb-469f-a165-70867728950e",
+            "name": "Bob",
+            "tags": ["a", "b", "c"]
+        })))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/show_snapshot_diff.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result.passed(), false);
+    let diff = result.requests.first().unwrap().diff.as_ref().unwrap();
+    let changed_lines = render_unified_diff(diff, false)
+        .lines()
+        .filter(|line| line.starts_with('-') || line.starts_with('+'))
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changed_lines,
+        [
+            "--- expected",
+            "+++ actual",
+            "-  \"name\": \"Alice\",",
+            "+  \"name\": \"Bob\","
+        ]
+    );
+}
+
+#[tokio::test]
+async fn junit_report() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/items/1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"id": "1"})))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/items/2"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({"id": "2"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/junit_report.md");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+
+    let missing = PathBuf::from("tests/e2e_inputs/missing.http");
+    let results = vec![
+        (path, result),
+        (missing, Err(HttpSnapError::UnsupportedFile(PathBuf::new()))),
+    ];
+    let xml = create_junit_report(&results);
+
+    assert!(xml.contains("<testsuites name=\"http-snap\" tests=\"3\" failures=\"1\" errors=\"1\""));
+    assert!(xml.contains(
+        "<testsuite name=\"tests/e2e_inputs/junit_report.md\" tests=\"2\" failures=\"1\""
+    ));
+    assert!(xml.contains("<testcase name=\"Items\" classname=\"tests/e2e_inputs/junit_report.md\""));
+    assert!(xml.contains("<testcase name=\"Get missing &lt;item&gt;\""));
+    assert!(xml.contains("<failure message=\"1 mismatch\" type=\"SnapshotMismatch\">/status: value differs, expected 200 but got 404"));
+    assert!(xml.contains(
+        "<testsuite name=\"tests/e2e_inputs/missing.http\" tests=\"1\" failures=\"0\" errors=\"1\""
+    ));
+}
+
+#[tokio::test]
+async fn json_report() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/tokens"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({"token": "abc"})))
+        .mount(&server)
+        .await;
+
+    let mut path = PathBuf::new();
+    path.push("tests/e2e_inputs/json_report.http");
+    let result = run(
+        &path,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await;
+
+    let report = create_json_report(&[(path, result)]).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["passed"], 1);
+    let file = &json["files"][0];
+    assert_eq!(file["path"], "tests/e2e_inputs/json_report.http");
+    assert_eq!(file["passed"], true);
+    assert_eq!(file["error"], serde_json::Value::Null);
+    let request = &file["requests"][0];
+    assert_eq!(request["verb"], "POST");
+    assert_eq!(request["url"], format!("{}/tokens", server.uri()));
+    assert_eq!(request["headers"]["x-client"], "http-snap");
+    assert_eq!(request["status"], 201);
+    assert!(request["duration_ms"].is_u64());
+    assert_eq!(request["matched_option"], 0);
+    assert_eq!(request["captured_variables"]["token_id"], "abc");
+    assert_eq!(request["mismatches"], json!([]));
+}
+
+#[tokio::test]
+async fn concurrent_files_keep_order() {
+    let server = MockServer::start().await;
+    // The first file finishes last, so its output is only in front when it is held back
+    Mock::given(method("GET"))
+        .and(path("/slow"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({"name": "changed"}))
+                .set_delay(Duration::from_millis(500)),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/fast"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"name": "fast"})))
+        .mount(&server)
+        .await;
+
+    let directory = common::TempDir::new();
+    let environment = directory.path.join("jobs.env");
+    std::fs::write(&environment, format!("@test_host = \"{}\"", server.uri())).unwrap();
+    let report = directory.path.join("report.json");
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_http-snap"))
+        .args(["test", "--jobs", "2", "--path"])
+        .arg("tests/e2e_inputs/jobs/*.http")
+        .arg("--environment")
+        .arg(&environment)
+        .arg("--report")
+        .arg(format!("json={}", report.display()))
+        .env("NO_COLOR", "1")
+        .output()
+        .await
+        .unwrap();
+    let json = std::fs::read_to_string(&report);
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let position = |text: &str| stderr.find(text).unwrap();
+    let slow_file = "tests/e2e_inputs/jobs/a_slow.http";
+    let fast_file = "tests/e2e_inputs/jobs/b_fast.http";
+    // Each file's output is written as one block, in the order of the files
+    assert!(position(&format!("Running \"{slow_file}\"")) < position("/body/name"));
+    assert!(position("/body/name") < position(&format!("Test \"{slow_file}\" failed")));
+    assert!(
+        position(&format!("Test \"{slow_file}\" failed"))
+            < position(&format!("Running \"{fast_file}\""))
+    );
+    assert!(position(&format!("Test \"{fast_file}\" passed")) < position("Ran 2 tests"));
+    assert!(position("Ran 2 tests") < position("The following failed"));
+    assert!(position("The following failed") < stderr.rfind(slow_file).unwrap());
+    assert_eq!(output.status.success(), false);
+    let json: serde_json::Value = serde_json::from_str(&json.unwrap()).unwrap();
+    assert_eq!(json["files"][0]["path"], slow_file);
+    assert_eq!(json["files"][1]["path"], fast_file);
+}
+
+#[tokio::test]
+async fn serve_snapshots() {
+    common::init_logger();
+    let paths = vec![PathBuf::from("tests/e2e_inputs/serve_snapshots.http")];
+    let routes = load_routes(&paths, &HashMap::new()).unwrap();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve(listener, routes, true));
+    let client = reqwest::Client::new();
+
+    let item = client.get(format!("{host}/items/42")).send().await.unwrap();
+    assert_eq!(item.status(), 200);
+    let correlation_id = item.headers()["correlation-id"].to_str().unwrap();
+    assert!(Uuid::parse_str(correlation_id).is_ok());
+    let body: serde_json::Value = item.json().await.unwrap();
+    assert!(Uuid::parse_str(body["id"].as_str().unwrap()).is_ok());
+    assert_eq!(body["name"], "Stored");
+
+    let created = client
+        .post(format!("{host}/items"))
+        .body(r#"{"name": "second"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 201);
+    assert_eq!(
+        created.json::<serde_json::Value>().await.unwrap(),
+        json!({"name": "second"})
+    );
+
+    // Snapshots of the same request are replayed in order, repeating the last one
+    for expected in [json!([]), json!(["first"]), json!(["first"])] {
+        let items = client.get(format!("{host}/items")).send().await.unwrap();
+        assert_eq!(items.json::<serde_json::Value>().await.unwrap(), expected);
+    }
+
+    // Values are made up for comparisons without an example
+    let stats = client.get(format!("{host}/stats")).send().await.unwrap();
+    assert_eq!(
+        stats.json::<serde_json::Value>().await.unwrap(),
+        json!({
+            "name": "",
+            "count": 0,
+            "tags": [],
+            "average": 2.5,
+            "total": 101,
+            "remaining": -0.5
+        })
+    );
+
+    let missing = client.get(format!("{host}/missing")).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let invalid = PathBuf::from("tests/e2e_inputs/serve_invalid_timestamp.http");
+    assert!(matches!(
+        load_routes(&vec![invalid], &HashMap::new()),
+        Err(HttpSnapError::InvalidBody(_))
+    ));
+}
+
+#[tokio::test]
+async fn record_exchanges() {
+    common::init_logger();
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/items"))
+        .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+            "id": "4f1c0a5e-8a0b-4b8e-9f5e-2d7a3b1c6e90",
+            "name": "Milk"
+        })))
+        .mount(&server)
+        .await;
+
+    let out = common::TempDir::new();
+    let options = RecordOptions {
+        target: server.uri(),
+        out: out.path.clone(),
+        host_variable: Some(String::from("test_host")),
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(record(listener, options));
+
+    let response = reqwest::Client::new()
+        .post(format!("{proxy}/items"))
+        .json(&json!({"name": "Milk"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap()["name"],
+        "Milk"
+    );
+
+    let recorded = out.path.join("001_post_items.http");
+    let content = std::fs::read_to_string(&recorded).unwrap();
+    assert!(content.starts_with("POST {{test_host}}/items\n"));
+    assert!(content.contains("\"id\": {{_:guid"));
+
+    // The recorded file replays as a passing test
+    let result = run(
+        &recorded,
+        &common::create_environment_variables(&server),
+        &ExecuteOptions::new_test(),
+        &ClientOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(result.passed(), true);
 }